@@ -12,6 +12,28 @@ variable "aws_region" {
   default = "us-east-2"
 }
 
+# Origins footer.imports may load from; keep in sync with DEFAULT_IMPORT_ORIGINS in src/imports.rs
+variable "import_origins" {
+  type = list(string)
+  default = [
+    "https://cdn.jsdelivr.net",
+    "https://kit.fontawesome.com",
+    "https://use.fontawesome.com",
+  ]
+}
+
+# Reject footer.imports tags without an integrity attribute
+variable "require_import_integrity" {
+  type    = bool
+  default = false
+}
+
+# Serve the template's CDN assets from copies uploaded to the generator bucket's vendor/ prefix
+variable "vendor_assets" {
+  type    = bool
+  default = false
+}
+
 provider "aws" {
   region = var.aws_region
 }
@@ -115,9 +137,38 @@ resource "aws_iam_role_policy_attachment" "basic_execution_role_attachment" {
 data "aws_iam_policy_document" "s3" {
   statement {
     actions = ["s3:PutObject"]
-    # Intentionally broad: grants write access to index.html in any bucket in the account
+    # Intentionally broad: grants write access to generated files in any bucket in the account
     # to support deploying to multiple site buckets without updating this policy per site.
-    resources = ["arn:aws:s3:::*/index.html"]
+    resources = [
+      "arn:aws:s3:::*/index.html",
+      "arn:aws:s3:::*/index.html.br",
+      "arn:aws:s3:::*/index.html.gz",
+      "arn:aws:s3:::*/??/index.html",
+      "arn:aws:s3:::*/??/index.html.br",
+      "arn:aws:s3:::*/??/index.html.gz",
+      "arn:aws:s3:::*/csp.json",
+      "arn:aws:s3:::*/assets/*",
+      "arn:aws:s3:::*/feed.xml",
+      "arn:aws:s3:::*/lists.json",
+      "arn:aws:s3:::*/lists.md",
+      "arn:aws:s3:::*/lists.txt",
+    ]
+  }
+
+  # Pages of languages a site no longer has.
+  statement {
+    actions = ["s3:DeleteObject"]
+    resources = [
+      "arn:aws:s3:::*/??/index.html",
+      "arn:aws:s3:::*/??/index.html.br",
+      "arn:aws:s3:::*/??/index.html.gz",
+    ]
+  }
+
+  # Per-site state between renders: the change feed's snapshot and log, and first-seen dates.
+  statement {
+    actions   = ["s3:PutObject"]
+    resources = ["${aws_s3_bucket.generator.arn}/history/*"]
   }
 
   statement {
@@ -180,6 +231,26 @@ resource "aws_lambda_permission" "allow_bucket" {
   source_arn    = aws_s3_bucket.generator.arn
 }
 
+# Re-renders sites whose publish_at/expire_at boundaries have passed since their last render.
+resource "aws_cloudwatch_event_rule" "schedule" {
+  name                = "list-of-lists-schedule"
+  description         = "Re-render list-of-lists sites on visibility boundaries"
+  schedule_expression = "rate(15 minutes)"
+}
+
+resource "aws_cloudwatch_event_target" "schedule" {
+  rule = aws_cloudwatch_event_rule.schedule.name
+  arn  = aws_lambda_function.lambda.arn
+}
+
+resource "aws_lambda_permission" "allow_schedule" {
+  statement_id  = "list-of-lists-allow-exec-from-events"
+  action        = "lambda:InvokeFunction"
+  function_name = aws_lambda_function.lambda.function_name
+  principal     = "events.amazonaws.com"
+  source_arn    = aws_cloudwatch_event_rule.schedule.arn
+}
+
 resource "aws_lambda_function" "lambda" {
   function_name = "list-of-lists"
   s3_bucket     = data.aws_s3_bucket.code_bucket.bucket
@@ -204,7 +275,10 @@ resource "aws_lambda_function" "lambda" {
 
   environment {
     variables = {
-      LOL_GENERATOR_BUCKET = aws_s3_bucket.generator.bucket
+      LOL_GENERATOR_BUCKET  = aws_s3_bucket.generator.bucket
+      LOL_IMPORT_ORIGINS    = join(",", var.import_origins)
+      LOL_REQUIRE_INTEGRITY = tostring(var.require_import_integrity)
+      LOL_VENDOR_ASSETS     = tostring(var.vendor_assets)
     }
   }
 }
@@ -92,6 +92,34 @@ resource "aws_s3_object" "favicon" {
   cache_control = "public, max-age=31536000, immutable"
 }
 
+# Response headers for every object.
+#
+# The Content-Security-Policy header is enforced alongside the page's <meta> CSP, so it only
+# carries directives that don't depend on the page; the hashes and origins that change with
+# every render stay in the <meta> CSP. A test in src/csp.rs checks it matches header_value.
+#
+# Vary: Accept-Encoding, since the precompressed function picks the body by that header; without
+# it, browser and proxy caches could serve brotli to viewers that can't decode it.
+
+resource "aws_cloudfront_response_headers_policy" "site" {
+  name = "${var.site_name}-security-headers"
+
+  custom_headers_config {
+    items {
+      header   = "Vary"
+      value    = "Accept-Encoding"
+      override = true
+    }
+  }
+
+  security_headers_config {
+    content_security_policy {
+      content_security_policy = "base-uri 'self'; form-action 'none'; object-src 'none'; frame-ancestors 'none'"
+      override                = true
+    }
+  }
+}
+
 # TLS certificate (must be in us-east-1 for CloudFront)
 
 resource "aws_acm_certificate" "cert" {
@@ -167,6 +195,40 @@ resource "aws_cloudfront_origin_access_control" "site_distribution_oac" {
   signing_protocol                  = "sigv4"
 }
 
+# Serves the generator's precompressed index.html (and <lang>/index.html) and assets/ variants (<path>.br, <path>.gz) to
+# viewers that accept them. The paths must match has_encoded_variants in src/generator.rs, or S3 would be asked for
+# variants that don't exist. Directory URLs like /es/ are served their index.html, which S3 origins don't do themselves,
+# and /es is redirected to /es/.
+resource "aws_cloudfront_function" "precompressed" {
+  name    = "${var.site_name}-precompressed"
+  runtime = "cloudfront-js-2.0"
+  publish = true
+  code    = <<-EOT
+    function handler(event) {
+      var request = event.request;
+      if (/^\/[a-z]{2}$/.test(request.uri)) {
+        return {
+          statusCode: 301,
+          statusDescription: 'Moved Permanently',
+          headers: { location: { value: request.uri + '/' } }
+        };
+      }
+      var uri = /^\/([a-z]{2}\/)?$/.test(request.uri) ? request.uri + 'index.html' : request.uri;
+      if (!/^\/([a-z]{2}\/)?index\.html$/.test(uri) && !/^\/assets\/[^\/]+\.(css|js|svg)$/.test(uri)) {
+        return request;
+      }
+      var header = request.headers['accept-encoding'];
+      var accepted = header ? header.value : '';
+      if (/(^|[\s,])br(;|,|\s|$)/.test(accepted)) {
+        request.uri = uri + '.br';
+      } else if (/(^|[\s,])gzip(;|,|\s|$)/.test(accepted)) {
+        request.uri = uri + '.gz';
+      }
+      return request;
+    }
+  EOT
+}
+
 resource "aws_cloudfront_distribution" "site" {
   origin {
     domain_name              = aws_s3_bucket.site.bucket_domain_name
@@ -194,10 +256,18 @@ resource "aws_cloudfront_distribution" "site" {
       }
     }
 
+    response_headers_policy_id = aws_cloudfront_response_headers_policy.site.id
+
+    function_association {
+      event_type   = "viewer-request"
+      function_arn = aws_cloudfront_function.precompressed.arn
+    }
+
+    # Objects carry their own Cache-Control (see publish in src/generator.rs); these only bound it.
     viewer_protocol_policy = "redirect-to-https"
-    min_ttl                = 3600
+    min_ttl                = 0
     default_ttl            = 86400
-    max_ttl                = 604800
+    max_ttl                = 31536000
     compress               = true
   }
 
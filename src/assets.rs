@@ -0,0 +1,345 @@
+use crate::generator::{Io, VENDOR_DIR};
+use crate::html::attr;
+use crate::sri::{integrity, matches_integrity, vendored_key};
+use anyhow::{Context, Result, anyhow, bail};
+use log::debug;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// Site-bucket prefix the vendored copies are published under.
+pub(crate) const ASSETS_DIR: &str = "assets";
+
+// Only the template's own CDN is vendored; other origins in footer.imports stay remote.
+const VENDORED_ORIGIN: &str = "https://cdn.jsdelivr.net/";
+
+#[derive(Debug)]
+pub(crate) struct Asset {
+    pub key: String,
+    pub content_type: &'static str,
+    pub content: Vec<u8>,
+}
+
+// A vendored script or stylesheet and what its references are rewritten to.
+struct Rewrite {
+    key: String,
+    original: Vec<u8>,
+    integrity: String,
+}
+
+// Assets published so far, and the key each vendored URL was published under.
+#[derive(Default)]
+struct Published {
+    assets: Vec<Asset>,
+    keys: HashMap<String, String>,
+}
+
+impl Published {
+    fn add(&mut self, url: &str, content: Vec<u8>) -> String {
+        let key = format!("{ASSETS_DIR}/{}", hashed_name(url, &content));
+        self.assets.push(Asset {
+            key: key.clone(),
+            content_type: content_type(url),
+            content,
+        });
+        self.keys.insert(url.to_string(), key.clone());
+        key
+    }
+}
+
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<(script|link)\b([^>]*)>").unwrap());
+static CSS_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"url\(\s*(?:"([^"]*)"|'([^']*)'|([^'")\s]+))\s*\)"#).unwrap());
+
+// Replaces references to the vendored CDN in a rendered page with content-hashed
+// copies from the generator bucket, returning the rewritten page and the assets
+// it now depends on. Relative url()s in vendored stylesheets, e.g. icon fonts,
+// are vendored and rewritten too, so integrity is recomputed for stylesheets.
+// `root` is the path from the page to the site root, e.g. "../" for a translation.
+pub(crate) async fn vendor(io: &Io, html: &str, root: &str) -> Result<(String, Vec<Asset>)> {
+    let mut published = Published::default();
+    let mut rewrites: HashMap<String, Rewrite> = HashMap::new();
+
+    for caps in TAG.captures_iter(html) {
+        let Some(url) = vendorable_url(&caps[1], &caps[2]) else {
+            continue;
+        };
+        if !rewrites.contains_key(&url) {
+            let original = read_vendored(io, &url).await?;
+            let content = match content_type(&url) {
+                "text/css" => rewrite_css(io, &url, &original, &mut published).await?,
+                _ => original.clone(),
+            };
+            let rewrite = Rewrite {
+                integrity: integrity(&content),
+                key: published.add(&url, content),
+                original,
+            };
+            rewrites.insert(url.clone(), rewrite);
+        }
+
+        // Integrity pins the CDN's bytes, so a stale or tampered copy fails here rather than in browsers.
+        if let Some(expected) = attr(&caps[2], "integrity")
+            && !matches_integrity(&rewrites[&url].original, &expected)
+        {
+            bail!("vendored copy of {url} does not match its integrity {expected:?}");
+        }
+    }
+
+    let html = TAG.replace_all(html, |caps: &regex::Captures| {
+        let Some(url) = vendorable_url(&caps[1], &caps[2]) else {
+            return caps[0].to_string();
+        };
+        let rewrite = &rewrites[&url];
+        let mut tag = caps[0].replace(&url, &format!("{root}{}", rewrite.key));
+        if let Some(expected) = attr(&caps[2], "integrity") {
+            tag = tag.replace(&expected, &rewrite.integrity);
+        }
+        tag
+    });
+
+    Ok((html.into_owned(), published.assets))
+}
+
+// Scripts and stylesheets (including preloaded ones) on the vendored CDN.
+fn vendorable_url(name: &str, attrs: &str) -> Option<String> {
+    let url = if name.eq_ignore_ascii_case("script") {
+        attr(attrs, "src")?
+    } else {
+        let rel = attr(attrs, "rel").unwrap_or_default().to_ascii_lowercase();
+        let as_ = attr(attrs, "as").unwrap_or_default().to_ascii_lowercase();
+        let stylesheet = rel.split_whitespace().any(|r| r == "stylesheet");
+        let preloaded_style = rel.split_whitespace().any(|r| r == "preload") && as_ == "style";
+        if !stylesheet && !preloaded_style {
+            return None;
+        }
+        attr(attrs, "href")?
+    };
+    url.starts_with(VENDORED_ORIGIN).then_some(url)
+}
+
+async fn read_vendored(io: &Io, url: &str) -> Result<Vec<u8>> {
+    let key = format!("{VENDOR_DIR}/{}", vendored_key(url)?);
+    debug!("Reading vendored copy of {url} from {key}");
+    io.read_bytes(&key)
+        .await
+        .with_context(|| format!("read vendored copy of {url}"))
+}
+
+// Vendors each relative url() in a stylesheet, pointing it at the published copy.
+// Assets are published side by side, so references are bare file names.
+async fn rewrite_css(
+    io: &Io,
+    css_url: &str,
+    css: &[u8],
+    published: &mut Published,
+) -> Result<Vec<u8>> {
+    let css = str::from_utf8(css).with_context(|| format!("{css_url} is not UTF-8"))?;
+
+    let mut rewritten: HashMap<String, String> = HashMap::new();
+    for caps in CSS_URL.captures_iter(css) {
+        let reference = css_reference(&caps);
+        if rewritten.contains_key(reference) || !is_relative(reference) {
+            continue;
+        }
+        let (path, fragment) = match reference.split_once('#') {
+            Some((path, fragment)) => (path, format!("#{fragment}")),
+            None => (reference, String::new()),
+        };
+        let url = resolve(css_url, path.split('?').next().unwrap_or_default())
+            .with_context(|| format!("resolve {reference:?} in {css_url}"))?;
+
+        let key = match published.keys.get(&url) {
+            Some(key) => key.clone(),
+            None => {
+                let content = read_vendored(io, &url).await?;
+                published.add(&url, content)
+            }
+        };
+        let name = key.rsplit('/').next().unwrap_or_default();
+        rewritten.insert(reference.to_string(), format!("{name}{fragment}"));
+    }
+
+    Ok(CSS_URL
+        .replace_all(css, |caps: &regex::Captures| {
+            match rewritten.get(css_reference(caps)) {
+                Some(name) => format!(r#"url("{name}")"#),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+        .into_bytes())
+}
+
+fn css_reference<'a>(caps: &regex::Captures<'a>) -> &'a str {
+    caps.get(1)
+        .or(caps.get(2))
+        .or(caps.get(3))
+        .map_or("", |m| m.as_str())
+}
+
+fn is_relative(reference: &str) -> bool {
+    !(reference.is_empty() || reference.starts_with(['/', '#']) || reference.contains(':'))
+}
+
+// Resolves a relative path against the directory of an absolute URL.
+fn resolve(base: &str, relative: &str) -> Result<String> {
+    let (dir, _) = base
+        .split(['?', '#'])
+        .next()
+        .and_then(|b| b.rsplit_once('/'))
+        .ok_or_else(|| anyhow!("{base:?} has no path"))?;
+    let (origin, dir) = match dir.find("://").map(|i| i + 3) {
+        Some(scheme_end) => match dir[scheme_end..].find('/') {
+            Some(i) => dir.split_at(scheme_end + i),
+            None => (dir, ""),
+        },
+        None => bail!("{base:?} is not an absolute URL"),
+    };
+
+    let mut segments: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in relative.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments
+                    .pop()
+                    .ok_or_else(|| anyhow!("{relative:?} escapes {origin}"))?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(format!("{origin}/{}", segments.join("/")))
+}
+
+// "bootstrap.min.css" becomes "bootstrap.min.<hash>.css".
+fn hashed_name(url: &str, content: &[u8]) -> String {
+    let file = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit('/').next())
+        .unwrap_or_default();
+    let hash: String = Sha256::digest(content)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    match file.rsplit_once('.') {
+        Some((stem, ext)) => format!("{stem}.{hash}.{ext}"),
+        None => format!("{file}.{hash}"),
+    }
+}
+
+fn content_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    match path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+    {
+        Some(ext) => match ext.as_str() {
+            "css" => "text/css",
+            "js" | "mjs" => "text/javascript",
+            "map" | "json" => "application/json",
+            "woff2" => "font/woff2",
+            "woff" => "font/woff",
+            "ttf" => "font/ttf",
+            "otf" => "font/otf",
+            "svg" => "image/svg+xml",
+            "png" => "image/png",
+            "gif" => "image/gif",
+            "ico" => "image/x-icon",
+            _ => "application/octet-stream",
+        },
+        None => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+    use tokio::fs;
+
+    const CSS: &str = r#"@font-face{src:url("./fonts/icons.woff2?abc") format("woff2"),url(fonts/icons.woff#x) format("woff")}.a{background:url(data:image/svg+xml,x)}"#;
+
+    async fn vendored_io(name: &str) -> Result<(Io, TempDir)> {
+        let dir = TempDir::new(&format!("assets-{name}"));
+        let vendor = dir.join("generator/vendor/cdn.jsdelivr.net/npm/icons@1");
+        fs::create_dir_all(vendor.join("fonts")).await?;
+        fs::write(vendor.join("icons.css"), CSS).await?;
+        fs::write(vendor.join("fonts/icons.woff2"), "woff2").await?;
+        fs::write(vendor.join("fonts/icons.woff"), "woff").await?;
+        fs::write(vendor.join("a.js"), "alert(1)").await?;
+        let io = dir.io();
+        Ok((io, dir))
+    }
+
+    #[tokio::test]
+    async fn test_vendor_rewrites_references() -> Result<()> {
+        let (io, _dir) = vendored_io("rewrite").await?;
+        let html = format!(
+            concat!(
+                r#"<link rel="preconnect" href="https://cdn.jsdelivr.net">"#,
+                r#"<link rel="preload" as="style" href="https://cdn.jsdelivr.net/npm/icons@1/icons.css" integrity="{}" crossorigin="anonymous">"#,
+                r#"<noscript><link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/icons@1/icons.css" integrity="{}" crossorigin="anonymous"></noscript>"#,
+                r#"<script defer src="https://cdn.jsdelivr.net/npm/icons@1/a.js"></script>"#,
+                r#"<script src="https://kit.fontawesome.com/a.js"></script>"#,
+            ),
+            integrity(CSS.as_bytes()),
+            integrity(CSS.as_bytes()),
+        );
+
+        let (html, assets) = vendor(&io, &html, "").await?;
+
+        assert_eq!(4, assets.len(), "{assets:?}");
+        let asset = |ext: &str| assets.iter().find(|a| a.key.ends_with(ext)).unwrap();
+        let (css, js) = (asset(".css"), asset(".js"));
+        let name = |ext: &str| asset(ext).key.strip_prefix("assets/").unwrap();
+        assert!(css.key.starts_with("assets/icons."));
+        assert_eq!("text/css", css.content_type);
+        assert_eq!("font/woff2", asset(".woff2").content_type);
+
+        let css_text = str::from_utf8(&css.content)?;
+        assert!(css_text.contains(&format!(r#"url("{}")"#, name(".woff2"))));
+        assert!(css_text.contains(&format!(r#"url("{}#x")"#, name(".woff"))));
+        assert!(css_text.contains("url(data:image/svg+xml,x)"));
+
+        assert!(!html.contains("cdn.jsdelivr.net/npm"), "{html}");
+        assert!(html.contains(r#"<link rel="preconnect" href="https://cdn.jsdelivr.net">"#));
+        assert!(html.contains("https://kit.fontawesome.com/a.js"));
+        assert!(html.contains(&format!(r#"src="{}""#, js.key)));
+        assert_eq!(2, html.matches(&format!(r#"href="{}""#, css.key)).count());
+        assert_eq!(2, html.matches(&integrity(&css.content)).count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_vendor_rejects_mismatched_integrity() -> Result<()> {
+        let (io, _dir) = vendored_io("mismatch").await?;
+        let html = format!(
+            r#"<script src="https://cdn.jsdelivr.net/npm/icons@1/a.js" integrity="{}" crossorigin></script>"#,
+            integrity(b"alert(2)")
+        );
+
+        let e = vendor(&io, &html, "").await.unwrap_err().to_string();
+        assert!(e.contains("does not match its integrity"), "{e}");
+
+        let missing = r#"<script src="https://cdn.jsdelivr.net/npm/icons@1/b.js"></script>"#;
+        assert!(vendor(&io, missing, "").await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            "https://a.com/npm/x/fonts/f.woff2",
+            resolve("https://a.com/npm/x/font/x.css?v=1", "../fonts/f.woff2").unwrap()
+        );
+        assert_eq!(
+            "https://a.com/x/f.woff2",
+            resolve("https://a.com/x/x.css", "./f.woff2").unwrap()
+        );
+        assert!(resolve("https://a.com/x.css", "../../f.woff2").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::include::{CSP_SOURCES, FOOTER, Includes, LISTS, THEME, include_value, take_includes};
+use crate::{Footer, ListOfLists, Theme};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+// The form `fmt` writes site JSON in: fields in declaration order, map keys sorted,
+// defaults omitted, and items as plain strings unless they need an object. The
+// model's own Serialize omits the defaults, so this only has to handle the items.
+pub fn to_canonical_json(list_of_lists: &ListOfLists) -> Result<String> {
+    to_canonical_json_with_includes(list_of_lists, &Includes::default())
+}
+
+// As to_canonical_json, with includes put back where they were taken from.
+fn to_canonical_json_with_includes(
+    list_of_lists: &ListOfLists,
+    includes: &Includes,
+) -> Result<String> {
+    let mut value = serde_json::to_value(with_stand_ins(list_of_lists, includes))
+        .context("serialize canonical site JSON")?;
+    for list in value[LISTS].as_array_mut().into_iter().flatten() {
+        for item in list["list"].as_array_mut().into_iter().flatten() {
+            if let Some(text) = item
+                .as_object()
+                .filter(|o| o.len() == 1)
+                .and_then(|o| o.get("item"))
+            {
+                *item = text.clone();
+            }
+        }
+    }
+
+    // Included fields hold stand-ins, so replacing them keeps the fields in place.
+    for (field, key) in &includes.fields {
+        value[field.as_str()] = include_value(key);
+    }
+    if let Some(lists) = value[LISTS].as_array_mut() {
+        // Positions are ascending, so inserting in order restores the original layout.
+        for (position, key) in &includes.lists {
+            lists.insert(*position, include_value(key));
+        }
+    }
+
+    let mut json = serde_json::to_string_pretty(&value).context("serialize canonical site JSON")?;
+    json.push('\n');
+    Ok(json)
+}
+
+// The site with a non-default value in each field that's included as a whole,
+// which serializes the field in its declared place.
+fn with_stand_ins(list_of_lists: &ListOfLists, includes: &Includes) -> ListOfLists {
+    let mut site = list_of_lists.clone();
+    for field in includes.fields.keys() {
+        match field.as_str() {
+            FOOTER => site.footer = Some(Footer::default()),
+            CSP_SOURCES => {
+                site.csp_sources.insert(String::new(), Vec::new());
+            }
+            THEME => site.theme = Some(Theme::default()),
+            _ => {}
+        }
+    }
+    site
+}
+
+// Parses site JSON and returns it in canonical form.
+pub fn canonicalize(content: &str) -> Result<String> {
+    edit(content, |_| true).map(Option::unwrap_or_default)
+}
+
+// Applies `edit` to site JSON and returns the result in canonical form, or None if
+// `edit` reports no change. Includes are kept as written rather than read.
+pub fn edit(content: &str, edit: impl FnOnce(&mut ListOfLists) -> bool) -> Result<Option<String>> {
+    let mut site: Value = serde_json::from_str(content).context("parse as JSON")?;
+    let includes = take_includes(&mut site);
+    let mut list_of_lists: ListOfLists =
+        serde_json::from_value(site).context("parse as ListOfLists")?;
+    if !edit(&mut list_of_lists) {
+        return Ok(None);
+    }
+    to_canonical_json_with_includes(&list_of_lists, &includes).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_canonical_form() -> Result<()> {
+        let canonical = canonicalize(
+            r#"{"lists": [{"list": ["A", {"item": "B"}, {"tooltip": "see", "item": "C"}],
+                 "hidden": false, "style": "ordered", "sort": "alpha", "title": "Letters"}],
+                "csp_sources": {"img-src": ["data:"], "font-src": ["https://a.com"]},
+                "footerLinks": [{"url": "https://example.com", "icon": "house", "title": null}],
+                "title": "Site"}"#,
+        )?;
+        assert_eq!(
+            r#"{
+  "title": "Site",
+  "lists": [
+    {
+      "title": "Letters",
+      "sort": "alpha",
+      "list": [
+        "A",
+        "B",
+        {
+          "item": "C",
+          "tooltip": "see"
+        }
+      ]
+    }
+  ],
+  "footer_links": [
+    {
+      "url": "https://example.com",
+      "icon": "house"
+    }
+  ],
+  "csp_sources": {
+    "font-src": [
+      "https://a.com"
+    ],
+    "img-src": [
+      "data:"
+    ]
+  }
+}
+"#,
+            canonical
+        );
+        // Formatting is idempotent.
+        assert_eq!(canonical, canonicalize(&canonical)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_keeps_includes_in_place() -> Result<()> {
+        let canonical = canonicalize(
+            r#"{"title": "Site", "lists": [{"include": "lists/a.json"},
+                {"list": ["B"], "title": "B"}, {"include": "lists/c.json"}],
+                "csp_sources": {"include": "shared/csp.json"},
+                "footer": {"include": "shared/footer.json"}}"#,
+        )?;
+        assert_eq!(
+            r#"{
+  "title": "Site",
+  "lists": [
+    {
+      "include": "lists/a.json"
+    },
+    {
+      "title": "B",
+      "list": [
+        "B"
+      ]
+    },
+    {
+      "include": "lists/c.json"
+    }
+  ],
+  "footer": {
+    "include": "shared/footer.json"
+  },
+  "csp_sources": {
+    "include": "shared/csp.json"
+  }
+}
+"#,
+            canonical
+        );
+
+        let canonical =
+            canonicalize(r#"{"title": "Site", "lists": {"include": "shared/lists.json"}}"#)?;
+        assert_eq!(canonical, canonicalize(&canonical)?);
+        assert!(
+            canonical.contains(r#""include": "shared/lists.json""#),
+            "{canonical}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_keeps_everything() -> Result<()> {
+        let original = r##"{
+            "title": "Site",
+            "description": "All of it",
+            "lists": [{
+                "title": "Everything",
+                "description": "Every field",
+                "icon": "bi bi-star",
+                "hidden": true,
+                "duplicates": true,
+                "duplicate_match": "case_folded",
+                "sort": "random_at_build",
+                "style": "ordered",
+                "start": 0,
+                "publish_at": "2026-01-01T00:00:00Z",
+                "expire_at": "2027-01-01T00:00:00Z",
+                "list": [{"item": "A", "id": "a", "tags": ["t"], "expire_at": "2026-06-01T00:00:00Z"}, "A", {"ref": "a"}],
+                "translations": {"es": {"title": "Todo", "description": "Cada campo",
+                    "list": ["Á", {"item": "Á", "tooltip": "ver"}, "Á"]}}
+            }],
+            "footer": {"imports": ["<script src=\"https://a.com/a.js\"></script>"], "links": []},
+            "new_item_days": 0,
+            "exports": ["json", "text"],
+            "unique_across_lists": "item_text",
+            "locale": "fr",
+            "translations": {"es": {"title": "Sitio", "description": "Todo"}},
+            "theme": {"primary_color": "#0d6efd", "color_scheme": "auto",
+                "font_family": "Inter, sans-serif", "custom_css": "styles/site.css"}
+        }"##;
+        let parsed: ListOfLists = serde_json::from_str(original)?;
+        let reparsed: ListOfLists = serde_json::from_str(&to_canonical_json(&parsed)?)?;
+        assert_eq!(parsed, reparsed);
+        Ok(())
+    }
+}
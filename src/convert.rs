@@ -0,0 +1,579 @@
+use crate::html::{attr, decode_entities};
+use crate::{DuplicateMatch, List, ListItem, ListOfLists, ListStyle, SortMode};
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SourceFormat {
+    Csv,
+    Markdown,
+    Opml,
+}
+
+impl SourceFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" => Some(SourceFormat::Csv),
+            "md" | "markdown" => Some(SourceFormat::Markdown),
+            "opml" => Some(SourceFormat::Opml),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SourceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(SourceFormat::Csv),
+            "markdown" => Ok(SourceFormat::Markdown),
+            "opml" => Ok(SourceFormat::Opml),
+            _ => bail!("unknown import format {s:?}; expected csv, markdown or opml"),
+        }
+    }
+}
+
+// A line of input that didn't make it into the site, and why.
+#[derive(Debug, PartialEq)]
+pub struct Skipped {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+#[derive(Debug)]
+pub struct Imported {
+    pub list_of_lists: ListOfLists,
+    pub skipped: Vec<Skipped>,
+}
+
+// Converts a spreadsheet export or outline into a validated site. `default_title`
+// names the site when the input doesn't.
+pub fn import(content: &str, format: SourceFormat, default_title: &str) -> Result<Imported> {
+    let mut builder = Builder::default();
+    match format {
+        SourceFormat::Csv => csv(content, &mut builder)?,
+        SourceFormat::Markdown => markdown(content, &mut builder),
+        SourceFormat::Opml => opml(content, &mut builder)?,
+    }
+
+    let list_of_lists = ListOfLists {
+        title: builder.title.unwrap_or_else(|| default_title.to_string()),
+        description: builder.description,
+        lists: builder.lists,
+        footer_links: Vec::new(),
+        footer: None,
+        csp_sources: BTreeMap::new(),
+        new_item_days: None,
+        exports: Vec::new(),
+        unique_across_lists: None,
+        locale: None,
+        translations: BTreeMap::new(),
+        theme: None,
+    }
+    .validate()
+    .context("imported site is invalid")?;
+
+    Ok(Imported {
+        list_of_lists,
+        skipped: builder.skipped,
+    })
+}
+
+#[derive(Default)]
+struct Builder {
+    title: Option<String>,
+    description: Option<String>,
+    lists: Vec<List>,
+    skipped: Vec<Skipped>,
+}
+
+impl Builder {
+    fn skip(&mut self, line: usize, reason: impl Into<String>) {
+        self.skipped.push(Skipped {
+            line,
+            reason: reason.into(),
+        });
+    }
+
+    // The index of the list with this title, created if it's new. Repeated titles
+    // share a list, as the site requires titles to be unique.
+    fn list(&mut self, title: &str) -> usize {
+        match self.lists.iter().position(|l| l.title == title) {
+            Some(index) => index,
+            None => {
+                self.lists.push(List {
+                    title: title.to_string(),
+                    description: None,
+                    icon: None,
+                    hidden: false,
+                    duplicates: false,
+                    duplicate_match: DuplicateMatch::Exact,
+                    sort: SortMode::default(),
+                    style: ListStyle::default(),
+                    start: None,
+                    publish_at: None,
+                    expire_at: None,
+                    list: Vec::new(),
+                    translations: BTreeMap::new(),
+                });
+                self.lists.len() - 1
+            }
+        }
+    }
+
+    // Adds an item, skipping blanks and repeats the site would reject.
+    fn item(&mut self, line: usize, list: Option<usize>, item: &str, tooltip: Option<&str>) {
+        let (item, tooltip) = (
+            item.trim(),
+            tooltip.map(str::trim).filter(|t| !t.is_empty()),
+        );
+        let Some(list) = list.map(|index| &mut self.lists[index]) else {
+            return self.skip(line, format!("item {item:?} is not under a list"));
+        };
+        if item.is_empty() {
+            return self.skip(line, "no item text");
+        }
+        if list.list.iter().any(|existing| existing.item() == item) {
+            let reason = format!("{item:?} is already in list {:?}", list.title);
+            return self.skip(line, reason);
+        }
+        list.list.push(match tooltip {
+            None => ListItem::Item(item.to_string()),
+            Some(tooltip) => ListItem::Detailed {
+                item: item.to_string(),
+                id: None,
+                tooltip: Some(tooltip.to_string()),
+                tags: Vec::new(),
+                publish_at: None,
+                expire_at: None,
+            },
+        });
+    }
+}
+
+// Columns are named by a header row (list, item and optionally tooltip, in any
+// order) or, without one, taken in that order.
+fn csv(content: &str, builder: &mut Builder) -> Result<()> {
+    let records = csv_records(content)?;
+    let mut records = records.into_iter().peekable();
+
+    let mut columns = (0, 1, Some(2));
+    if let Some((_, header)) = records.peek() {
+        let position = |name: &str| {
+            header
+                .iter()
+                .position(|cell| cell.trim().eq_ignore_ascii_case(name))
+        };
+        if let (Some(list), Some(item)) = (position("list"), position("item")) {
+            columns = (list, item, position("tooltip"));
+            records.next();
+        }
+    }
+
+    let (list_column, item_column, tooltip_column) = columns;
+    for (line, record) in records {
+        let cell = |i: usize| record.get(i).map_or("", |cell| cell.trim());
+        let list = cell(list_column);
+        if list.is_empty() {
+            builder.skip(line, "no list title");
+            continue;
+        }
+        let list = builder.list(list);
+        builder.item(
+            line,
+            Some(list),
+            cell(item_column),
+            tooltip_column.map(cell),
+        );
+    }
+    Ok(())
+}
+
+// RFC 4180 records with the line each starts on; quoted fields may contain
+// commas, doubled quotes and newlines. Blank lines are ignored.
+fn csv_records(content: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut records = Vec::new();
+    let (mut record, mut field) = (Vec::new(), String::new());
+    let (mut line, mut start, mut quoted) = (1, 1, false);
+
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|f| !f.is_empty()) {
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                record.clear();
+                line += 1;
+                start = line;
+            }
+            (_, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        bail!("line {start}: unterminated quoted field");
+    }
+    record.push(field);
+    if record.iter().any(|f| !f.is_empty()) {
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+// The first `#` heading names the site and later headings start lists. Bullets,
+// numbers and `- [ ]` checkboxes set each list's style, and `[^label]` footnotes
+// become tooltips, so `export --format markdown` output reads back in.
+fn markdown(content: &str, builder: &mut Builder) {
+    static HEADING: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(#{1,6})\s+(.*?)(?:\s+#+)?\s*$").unwrap());
+    static ITEM: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(\s*)(?:[-*+]|(\d+)[.)])\s+(\[[ xX]\]\s+)?(.*)$").unwrap());
+    static FOOTNOTE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\[\^([^\]]+)\]:\s*(.*)$").unwrap());
+    static FOOTNOTE_REF: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\[\^([^\]]+)\]\s*$").unwrap());
+
+    let footnotes: HashMap<&str, &str> = content
+        .lines()
+        .filter_map(|line| FOOTNOTE.captures(line.trim_end()))
+        .map(|caps| {
+            let (label, text) = (caps.get(1).unwrap(), caps.get(2).unwrap());
+            (label.as_str(), text.as_str())
+        })
+        .collect();
+
+    // Paragraph text directly under a heading describes the site or list.
+    let (mut current, mut describing) = (None, false);
+    for (i, line) in content.lines().enumerate() {
+        let (number, line) = (i + 1, line.trim_end());
+        if line.is_empty() || FOOTNOTE.is_match(line) {
+            continue;
+        }
+
+        if let Some(caps) = HEADING.captures(line) {
+            let text = unescape_markdown(&caps[2]);
+            if caps[1].len() == 1 && builder.title.is_none() && builder.lists.is_empty() {
+                builder.title = Some(text);
+            } else {
+                current = Some(builder.list(&text));
+            }
+            describing = true;
+        } else if let Some(caps) = ITEM.captures(line) {
+            describing = false;
+            if !caps[1].is_empty() {
+                builder.skip(number, "nested list items aren't supported");
+                continue;
+            }
+            let mut text = caps[4].to_string();
+            let mut tooltip = None;
+            if let Some(reference) = FOOTNOTE_REF.captures(&caps[4]) {
+                match footnotes.get(&reference[1]) {
+                    Some(note) => {
+                        tooltip = Some(unescape_markdown(note));
+                        text.truncate(reference.get(0).unwrap().start());
+                    }
+                    None => {
+                        builder.skip(number, format!("footnote {:?} is missing", &reference[0]))
+                    }
+                }
+            }
+            if let Some(list) = current.map(|index| &mut builder.lists[index])
+                && list.list.is_empty()
+            {
+                list.style = match (caps.get(2), caps.get(3)) {
+                    (_, Some(_)) => ListStyle::Checklist,
+                    (Some(n), None) => {
+                        let n = n.as_str().parse().unwrap_or(1);
+                        list.start = (n != 1).then_some(n);
+                        ListStyle::Ordered
+                    }
+                    (None, None) => ListStyle::Unordered,
+                };
+            }
+            builder.item(
+                number,
+                current,
+                &unescape_markdown(&text),
+                tooltip.as_deref(),
+            );
+        } else if describing {
+            let text = unescape_markdown(line.trim());
+            let description = match current {
+                Some(index) => &mut builder.lists[index].description,
+                None => &mut builder.description,
+            };
+            *description = Some(match description.take() {
+                Some(previous) => format!("{previous} {text}"),
+                None => text,
+            });
+        } else {
+            builder.skip(number, "not a heading, list item or description");
+        }
+    }
+}
+
+fn unescape_markdown(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && next.is_ascii_punctuation() => {
+                unescaped.push(*next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped.trim().to_string()
+}
+
+// Top-level outlines are lists and their children items; `_note` attributes
+// become descriptions and tooltips.
+fn opml(content: &str, builder: &mut Builder) -> Result<()> {
+    static TITLE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)<head\b.*?<title>(.*?)</title>").unwrap());
+    static OUTLINE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)<(/?)outline\b([^>]*?)(/?)>").unwrap());
+
+    if !content.contains("<opml") {
+        bail!("not an OPML document");
+    }
+    if let Some(caps) = TITLE.captures(content) {
+        let title = decode_entities(caps[1].trim());
+        builder.title = (!title.is_empty()).then_some(title);
+    }
+
+    let (mut current, mut depth) = (None, 0);
+    for caps in OUTLINE.captures_iter(content) {
+        if !caps[1].is_empty() {
+            depth -= 1;
+            continue;
+        }
+        let line = content[..caps.get(0).unwrap().start()]
+            .matches('\n')
+            .count()
+            + 1;
+        let attrs = &caps[2];
+        let text = attr(attrs, "text")
+            .or_else(|| attr(attrs, "title"))
+            .unwrap_or_default();
+        let note = attr(attrs, "_note");
+
+        match depth {
+            0 if text.trim().is_empty() => {
+                current = None;
+                builder.skip(line, "outline has no text");
+            }
+            0 => {
+                let index = builder.list(text.trim());
+                current = Some(index);
+                if let Some(note) = note.filter(|n| !n.trim().is_empty()) {
+                    builder.lists[index].description = Some(note);
+                }
+            }
+            1 => builder.item(line, current, &text, note.as_deref()),
+            _ => builder.skip(line, format!("outline {text:?} is nested below an item")),
+        }
+        if caps[3].is_empty() {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn items(list: &List) -> Vec<&str> {
+        list.list.iter().map(ListItem::item).collect()
+    }
+
+    #[test]
+    fn test_csv_with_header_and_quotes() -> Result<()> {
+        let imported = import(
+            "Tooltip,List,Item\r\n\
+             ,Letters,A\r\n\
+             \"Says \"\"hi\"\"\",Numbers,\"1, the first\"\r\n\
+             ,Letters,B\r\n\
+             ,,C\r\n\
+             ,Letters,A\r\n",
+            SourceFormat::Csv,
+            "example.com",
+        )?;
+
+        let lol = &imported.list_of_lists;
+        assert_eq!("example.com", lol.title);
+        assert_eq!(vec!["A", "B"], items(&lol.lists[0]));
+        assert_eq!(
+            ListItem::Detailed {
+                item: "1, the first".to_string(),
+                id: None,
+                tooltip: Some("Says \"hi\"".to_string()),
+                tags: Vec::new(),
+                publish_at: None,
+                expire_at: None,
+            },
+            lol.lists[1].list[0]
+        );
+        assert_eq!(
+            vec![5, 6],
+            imported.skipped.iter().map(|s| s.line).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_without_header_is_positional() -> Result<()> {
+        let imported = import("Letters,A,first\nLetters,B\n", SourceFormat::Csv, "x")?;
+        assert_eq!(vec!["A", "B"], items(&imported.list_of_lists.lists[0]));
+        assert!(import("Letters,\"A\n", SourceFormat::Csv, "x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_reads_back_markdown_export() -> Result<()> {
+        let imported = import(
+            r#"# Food & \*Drink\*
+
+What we eat
+
+## Ranked
+
+0. Soup
+1. Steak[^1]
+
+## Shopping
+
+This week
+
+- [ ] Eggs
+
+## Snacks
+
+- Nuts[^2]
+  - Salted
+Some stray text
+
+[^1]: Medium \[rare\]
+[^2]: Salted
+"#,
+            SourceFormat::Markdown,
+            "x",
+        )?;
+
+        let lol = &imported.list_of_lists;
+        assert_eq!("Food & *Drink*", lol.title);
+        assert_eq!(Some("What we eat"), lol.description.as_deref());
+        assert_eq!(
+            vec!["Ranked", "Shopping", "Snacks"],
+            lol.lists
+                .iter()
+                .map(|l| l.title.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(ListStyle::Ordered, lol.lists[0].style);
+        assert_eq!(Some(0), lol.lists[0].start);
+        assert_eq!(
+            ListItem::Detailed {
+                item: "Steak".to_string(),
+                id: None,
+                tooltip: Some("Medium [rare]".to_string()),
+                tags: Vec::new(),
+                publish_at: None,
+                expire_at: None,
+            },
+            lol.lists[0].list[1]
+        );
+        assert_eq!(ListStyle::Checklist, lol.lists[1].style);
+        assert_eq!(Some("This week"), lol.lists[1].description.as_deref());
+        assert_eq!(ListStyle::Unordered, lol.lists[2].style);
+        assert_eq!(
+            vec![
+                Skipped {
+                    line: 19,
+                    reason: "nested list items aren't supported".to_string()
+                },
+                Skipped {
+                    line: 20,
+                    reason: "not a heading, list item or description".to_string()
+                },
+            ],
+            imported.skipped
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_opml_outlines() -> Result<()> {
+        let imported = import(
+            r#"<?xml version="1.0"?>
+<opml version="2.0">
+  <head><title>Reading &amp; Watching</title></head>
+  <body>
+    <outline text="Books" _note="To read">
+      <outline text="Dune" _note="Herbert"/>
+      <outline text="Emma">
+        <outline text="Chapter 1"/>
+      </outline>
+    </outline>
+    <outline text="Films"><outline text="Alien"/></outline>
+  </body>
+</opml>"#,
+            SourceFormat::Opml,
+            "x",
+        )?;
+
+        let lol = &imported.list_of_lists;
+        assert_eq!("Reading & Watching", lol.title);
+        assert_eq!(Some("To read"), lol.lists[0].description.as_deref());
+        assert_eq!(vec!["Dune", "Emma"], items(&lol.lists[0]));
+        assert_eq!(vec!["Alien"], items(&lol.lists[1]));
+        assert_eq!(
+            vec![8],
+            imported.skipped.iter().map(|s| s.line).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_validates_result() {
+        assert!(import("# Only a title\n", SourceFormat::Markdown, "x").is_err());
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            Some(SourceFormat::Markdown),
+            SourceFormat::from_path(Path::new("notes/Lists.MD"))
+        );
+        assert_eq!(None, SourceFormat::from_path(Path::new("lists.txt")));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::html::{attr, attributes, url_origin};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use regex::Regex;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+// Directives sites may extend via `csp_sources`; the rest are fixed.
+pub const EXTENSIBLE_DIRECTIVES: &[&str] = &[
+    "script-src",
+    "style-src",
+    "img-src",
+    "font-src",
+    "connect-src",
+];
+
+const SELF: &str = "'self'";
+
+// Directives that don't depend on the page's content. site-module/main.tf sets the
+// header as a literal rather than per render, so it's limited to these; hashes and
+// origins are left to the <meta> policy, which always matches the page it's in.
+const HEADER_DIRECTIVES: &[&str] = &["base-uri", "form-action", "object-src"];
+
+// A Content-Security-Policy derived from a fully rendered page, so its hashes
+// match the bytes the browser sees.
+#[derive(Debug, Default)]
+pub struct ContentSecurityPolicy {
+    directives: BTreeMap<&'static str, BTreeSet<String>>,
+}
+
+impl ContentSecurityPolicy {
+    pub fn from_html(html: &str, extra_sources: &BTreeMap<String, Vec<String>>) -> Self {
+        let mut csp = Self::default();
+        csp.add("default-src", SELF);
+        csp.add("object-src", "'none'");
+        csp.add("base-uri", SELF);
+        csp.add("form-action", "'none'");
+        for directive in EXTENSIBLE_DIRECTIVES {
+            csp.add(directive, SELF);
+        }
+        // Bootstrap's CSS inlines its SVG icons (e.g. navbar-toggler) as data: URIs.
+        csp.add("img-src", "data:");
+
+        for caps in INLINE_SCRIPT.captures_iter(html) {
+            if attr(&caps[1], "src").is_none() {
+                csp.add("script-src", &hash(&caps[2]));
+            }
+        }
+        for caps in INLINE_STYLE.captures_iter(html) {
+            csp.add("style-src", &hash(&caps[1]));
+        }
+
+        for caps in TAG.captures_iter(html) {
+            let (name, attrs) = (caps[1].to_ascii_lowercase(), &caps[2]);
+            csp.add_inline_attributes(attrs);
+
+            match name.as_str() {
+                "script" => csp.add_url("script-src", attr(attrs, "src")),
+                "img" => csp.add_url("img-src", attr(attrs, "src")),
+                "link" => {
+                    let rel = attr(attrs, "rel").unwrap_or_default().to_ascii_lowercase();
+                    let as_ = attr(attrs, "as").unwrap_or_default().to_ascii_lowercase();
+                    let href = attr(attrs, "href");
+                    if rel.split_whitespace().any(|r| r == "stylesheet") || as_ == "style" {
+                        // Stylesheets reference fonts relative to themselves.
+                        csp.add_url("style-src", href.clone());
+                        csp.add_url("font-src", href);
+                    } else if rel.split_whitespace().any(|r| r == "icon") {
+                        csp.add_url("img-src", href);
+                    } else if as_ == "script" {
+                        csp.add_url("script-src", href);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (directive, sources) in extra_sources {
+            if let Some(directive) = EXTENSIBLE_DIRECTIVES.iter().find(|d| *d == directive) {
+                for source in sources {
+                    csp.add(directive, source);
+                }
+            }
+        }
+
+        csp
+    }
+
+    // Allows whatever `other` allows too, for a header that covers several pages.
+    pub fn merge(&mut self, other: &Self) {
+        for (directive, sources) in &other.directives {
+            self.directives
+                .entry(directive)
+                .or_default()
+                .extend(sources.iter().cloned());
+        }
+    }
+
+    // For <meta http-equiv>; frame-ancestors is omitted because browsers ignore it there.
+    pub fn meta_value(&self) -> String {
+        self.render(false)
+    }
+
+    pub fn header_value(&self) -> String {
+        self.render(true)
+    }
+
+    // Machine-readable form: the header site-module/main.tf sets, and the directives
+    // of the pages' <meta> policies.
+    pub fn manifest(&self) -> Value {
+        json!({
+            "content_security_policy": self.header_value(),
+            "directives": self.directives,
+        })
+    }
+
+    fn render(&self, header: bool) -> String {
+        let mut directives: Vec<String> = self
+            .directives
+            .iter()
+            .filter(|(directive, _)| !header || HEADER_DIRECTIVES.contains(directive))
+            .map(|(directive, sources)| {
+                let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+                format!("{directive} {}", sources.join(" "))
+            })
+            .collect();
+        if header {
+            directives.push("frame-ancestors 'none'".to_string());
+        }
+        directives.join("; ")
+    }
+
+    fn add(&mut self, directive: &'static str, source: &str) {
+        self.directives
+            .entry(directive)
+            .or_default()
+            .insert(source.to_string());
+    }
+
+    fn add_url(&mut self, directive: &'static str, url: Option<String>) {
+        if let Some(source) = url.as_deref().and_then(source_for_url) {
+            self.add(directive, &source);
+        }
+    }
+
+    // Inline event handlers and style attributes can only be allowed by hash
+    // with 'unsafe-hashes'; the stylesheet preload's onload relies on this.
+    fn add_inline_attributes(&mut self, attrs: &str) {
+        for (name, value) in attributes(attrs) {
+            let directive = if name.starts_with("on") {
+                "script-src"
+            } else if name == "style" {
+                "style-src"
+            } else {
+                continue;
+            };
+            self.add(directive, "'unsafe-hashes'");
+            self.add(directive, &hash(&value));
+        }
+    }
+}
+
+static INLINE_SCRIPT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script\s*>").unwrap());
+static INLINE_STYLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<style\b[^>]*>(.*?)</style\s*>").unwrap());
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<([a-z][a-z0-9-]*)\b([^>]*)>").unwrap());
+fn hash(content: &str) -> String {
+    format!(
+        "'sha256-{}'",
+        STANDARD.encode(Sha256::digest(content.as_bytes()))
+    )
+}
+
+// Absolute http(s) URLs map to their origin, data: URIs to the data: scheme, and
+// anything else is same-origin and already covered by 'self'.
+fn source_for_url(url: &str) -> Option<String> {
+    if url
+        .trim()
+        .get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+    {
+        return Some("data:".to_string());
+    }
+    let (scheme, host) = url_origin(url)?;
+    Some(match scheme {
+        Some(scheme) => format!("{scheme}://{host}"),
+        // Protocol-relative URLs inherit the page's scheme, as does a scheme-less host-source.
+        None => host,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn directive(csp: &ContentSecurityPolicy, name: &str) -> Vec<String> {
+        csp.directives[name].iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_hashes_inline_scripts_and_styles() {
+        let html = concat!(
+            "<style>body{}</style>",
+            "<script>alert(1)</script>",
+            r#"<script type="application/ld+json">{}</script>"#,
+            r#"<script src="https://cdn.example.com/a.js"></script>"#,
+        );
+        let csp = ContentSecurityPolicy::from_html(html, &BTreeMap::new());
+
+        assert!(directive(&csp, "style-src").contains(&hash("body{}")));
+        let script_src = directive(&csp, "script-src");
+        assert!(script_src.contains(&hash("alert(1)")));
+        assert!(script_src.contains(&hash("{}")));
+        assert!(script_src.contains(&"https://cdn.example.com".to_string()));
+        assert!(!script_src.contains(&hash("")));
+    }
+
+    #[test]
+    fn test_known_hash() {
+        // echo -n 'alert(1)' | openssl dgst -sha256 -binary | base64
+        assert_eq!(
+            "'sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI='",
+            hash("alert(1)")
+        );
+    }
+
+    #[test]
+    fn test_collects_origins_by_resource_type() {
+        let html = concat!(
+            r#"<link rel="stylesheet" href="https://css.example.com/a.css">"#,
+            r#"<link rel="preload" as="style" href="//fonts.example.com/b.css">"#,
+            r#"<link rel="icon" href="images/favicon.ico">"#,
+            r#"<img src="https://img.example.com:8443/x.png">"#,
+            r#"<a href="https://nav.example.com/">nav</a>"#,
+        );
+        let csp = ContentSecurityPolicy::from_html(html, &BTreeMap::new());
+
+        let style_src = directive(&csp, "style-src");
+        assert!(style_src.contains(&"https://css.example.com".to_string()));
+        assert!(style_src.contains(&"fonts.example.com".to_string()));
+        assert!(directive(&csp, "font-src").contains(&"https://css.example.com".to_string()));
+        assert!(directive(&csp, "img-src").contains(&"https://img.example.com:8443".to_string()));
+        assert!(!csp.meta_value().contains("nav.example.com"));
+    }
+
+    #[test]
+    fn test_inline_event_handlers_use_unsafe_hashes() {
+        // Quoted as rendered, and unquoted as minify-html emits it.
+        for html in [
+            r#"<link rel="preload" as="style" href="a.css" onload="this.onload=null;this.rel='stylesheet'">"#,
+            r#"<link as=style href=a.css onload=this.onload=null;this.rel='stylesheet' rel=preload>"#,
+        ] {
+            let csp = ContentSecurityPolicy::from_html(html, &BTreeMap::new());
+
+            let script_src = directive(&csp, "script-src");
+            assert!(script_src.contains(&"'unsafe-hashes'".to_string()));
+            assert!(
+                script_src.contains(&hash("this.onload=null;this.rel='stylesheet'")),
+                "{html}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_extra_sources_only_extend_known_directives() {
+        let extra = BTreeMap::from([
+            (
+                "font-src".to_string(),
+                vec!["https://fonts.example.com".to_string()],
+            ),
+            (
+                "frame-src".to_string(),
+                vec!["https://evil.example.com".to_string()],
+            ),
+        ]);
+        let csp = ContentSecurityPolicy::from_html("", &extra);
+
+        assert!(directive(&csp, "font-src").contains(&"https://fonts.example.com".to_string()));
+        assert!(!csp.meta_value().contains("evil.example.com"));
+    }
+
+    #[test]
+    fn test_frame_ancestors_only_in_header() {
+        let csp = ContentSecurityPolicy::from_html("", &BTreeMap::new());
+
+        assert!(csp.header_value().contains("frame-ancestors 'none'"));
+        assert!(!csp.meta_value().contains("frame-ancestors"));
+        assert!(
+            csp.meta_value()
+                .starts_with("base-uri 'self'; connect-src 'self'")
+        );
+    }
+
+    #[test]
+    fn test_header_has_no_content_dependent_directives() {
+        let csp = ContentSecurityPolicy::from_html(
+            r#"<script>a()</script><img src="https://img.example.com/x.png">"#,
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(
+            "base-uri 'self'; form-action 'none'; object-src 'none'; frame-ancestors 'none'",
+            csp.header_value()
+        );
+        // The CloudFront response headers policy carries the same value.
+        let terraform = include_str!("../site-module/main.tf");
+        assert!(
+            terraform.contains(&format!("\"{}\"", csp.header_value())),
+            "site-module/main.tf"
+        );
+    }
+
+    #[test]
+    fn test_merge_allows_both_pages() {
+        let mut csp = ContentSecurityPolicy::from_html("<script>a()</script>", &BTreeMap::new());
+        csp.merge(&ContentSecurityPolicy::from_html(
+            "<script>b()</script>",
+            &BTreeMap::new(),
+        ));
+        let script_src = directive(&csp, "script-src");
+        assert!(script_src.contains(&hash("a()")));
+        assert!(script_src.contains(&hash("b()")));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::generator::sanitized_div_id;
+use crate::{ExportFormat, List, ListItem, ListOfLists, ListStyle};
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::str::FromStr;
+
+// Bumped whenever a field is removed or changes meaning; new fields don't bump it.
+const SCHEMA_VERSION: u32 = 1;
+
+impl ExportFormat {
+    // Published next to index.html.
+    pub(crate) fn file_name(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "lists.json",
+            ExportFormat::Markdown => "lists.md",
+            ExportFormat::Text => "lists.txt",
+        }
+    }
+
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+// The same names as in the site JSON, for the CLI.
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" => Ok(ExportFormat::Markdown),
+            "text" => Ok(ExportFormat::Text),
+            _ => bail!("unknown export format {s:?}; expected json, markdown or text"),
+        }
+    }
+}
+
+// Visible lists only; callers apply visible_at and sorted first, as for the page.
+pub(crate) fn export(
+    format: ExportFormat,
+    site_url: &str,
+    list_of_lists: &ListOfLists,
+) -> Result<Vec<u8>> {
+    match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(&Api::new(site_url, list_of_lists))
+            .context("serialize JSON export"),
+        ExportFormat::Markdown => Ok(markdown(list_of_lists).into_bytes()),
+        ExportFormat::Text => Ok(text(list_of_lists).into_bytes()),
+    }
+}
+
+fn visible_lists(list_of_lists: &ListOfLists) -> impl Iterator<Item = &List> {
+    list_of_lists.lists.iter().filter(|list| !list.hidden)
+}
+
+fn tooltip(item: &ListItem) -> Option<&str> {
+    match item {
+        ListItem::Item(_) | ListItem::Reference { .. } => None,
+        ListItem::Detailed { tooltip, .. } => tooltip.as_deref(),
+    }
+}
+
+// Each item's prefix in the list's style: ordered lists count up from start.
+fn markers(list: &List, checkbox: &'static str) -> impl Iterator<Item = String> {
+    let start = list.start.unwrap_or(1);
+    let style = list.style;
+    (0..).map(move |i| match style {
+        ListStyle::Ordered => format!("{}.", start + i),
+        ListStyle::Checklist => checkbox.to_string(),
+        ListStyle::Unordered | ListStyle::Inline => "-".to_string(),
+    })
+}
+
+// Item text is escaped so it reads literally; tooltips become numbered footnotes.
+fn markdown(list_of_lists: &ListOfLists) -> String {
+    let mut out = format!("# {}\n", markdown_escape(&list_of_lists.title));
+    if let Some(description) = &list_of_lists.description {
+        out.push_str(&format!("\n{}\n", markdown_escape(description)));
+    }
+
+    let mut footnotes = Vec::new();
+    for list in visible_lists(list_of_lists) {
+        out.push_str(&format!("\n## {}\n", markdown_escape(&list.title)));
+        if let Some(description) = &list.description {
+            out.push_str(&format!("\n{}\n", markdown_escape(description)));
+        }
+        out.push('\n');
+        for (item, marker) in list.list.iter().zip(markers(list, "- [ ]")) {
+            out.push_str(&format!("{marker} {}", markdown_escape(item.item())));
+            if let Some(tooltip) = tooltip(item) {
+                footnotes.push(tooltip);
+                out.push_str(&format!("[^{}]", footnotes.len()));
+            }
+            out.push('\n');
+        }
+    }
+
+    if !footnotes.is_empty() {
+        out.push('\n');
+        for (i, tooltip) in footnotes.iter().enumerate() {
+            out.push_str(&format!("[^{}]: {}\n", i + 1, markdown_escape(tooltip)));
+        }
+    }
+    out
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Underlined headings, with tooltips in parentheses after their items.
+fn text(list_of_lists: &ListOfLists) -> String {
+    let heading = |title: &str, underline: char| {
+        let rule: String = std::iter::repeat_n(underline, title.chars().count()).collect();
+        format!("{title}\n{rule}\n")
+    };
+
+    let mut out = heading(&list_of_lists.title, '=');
+    if let Some(description) = &list_of_lists.description {
+        out.push_str(&format!("\n{description}\n"));
+    }
+
+    for list in visible_lists(list_of_lists) {
+        out.push('\n');
+        out.push_str(&heading(&list.title, '-'));
+        if let Some(description) = &list.description {
+            out.push_str(&format!("\n{description}\n"));
+        }
+        out.push('\n');
+        for (item, marker) in list.list.iter().zip(markers(list, "[ ]")) {
+            match tooltip(item) {
+                Some(tooltip) => out.push_str(&format!("{marker} {} ({tooltip})\n", item.item())),
+                None => out.push_str(&format!("{marker} {}\n", item.item())),
+            }
+        }
+    }
+    out
+}
+
+#[derive(Debug, Serialize)]
+struct Api<'a> {
+    version: u32,
+    url: String,
+    title: &'a str,
+    description: Option<&'a str>,
+    lists: Vec<ApiList<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiList<'a> {
+    // The list's tab id on the page, so <url>#tab_<id> links to it.
+    id: String,
+    title: &'a str,
+    description: Option<&'a str>,
+    icon: Option<&'a str>,
+    style: ListStyle,
+    start: Option<i64>,
+    items: Vec<ApiItem<'a>>,
+}
+
+// Every item has the same shape, whether the site wrote it as a string or an object.
+#[derive(Debug, Serialize)]
+struct ApiItem<'a> {
+    text: &'a str,
+    tooltip: Option<&'a str>,
+    tags: &'a [String],
+}
+
+impl<'a> Api<'a> {
+    fn new(site_url: &str, list_of_lists: &'a ListOfLists) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            url: format!("https://{site_url}/"),
+            title: &list_of_lists.title,
+            description: list_of_lists.description.as_deref(),
+            lists: visible_lists(list_of_lists)
+                .map(|list| ApiList {
+                    id: sanitized_div_id(list.title.as_str()),
+                    title: &list.title,
+                    description: list.description.as_deref(),
+                    icon: list.icon.as_deref(),
+                    style: list.style,
+                    start: list.start,
+                    items: list.list.iter().map(ApiItem::new).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'a> ApiItem<'a> {
+    fn new(item: &'a ListItem) -> Self {
+        Self {
+            text: item.item(),
+            tooltip: tooltip(item),
+            tags: item.tags(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn test_json_normalizes_items_and_skips_hidden_lists() -> Result<()> {
+        let list_of_lists: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [
+                {"title": "Secret", "hidden": true, "list": ["Cake"]},
+                {"title": "Dinner Ideas", "style": "unordered", "list": [
+                    "Bread",
+                    {"item": "Soup", "tooltip": "Hot", "tags": ["veg"]}
+                ]}
+            ]}"#,
+        )?;
+
+        let exported: Value =
+            serde_json::from_slice(&export(ExportFormat::Json, "food.list", &list_of_lists)?)?;
+        assert_eq!(
+            json!({
+                "version": SCHEMA_VERSION,
+                "url": "https://food.list/",
+                "title": "Food",
+                "description": null,
+                "lists": [{
+                    "id": "Dinner_Ideas",
+                    "title": "Dinner Ideas",
+                    "description": null,
+                    "icon": null,
+                    "style": "unordered",
+                    "start": null,
+                    "items": [
+                        {"text": "Bread", "tooltip": null, "tags": []},
+                        {"text": "Soup", "tooltip": "Hot", "tags": ["veg"]}
+                    ]
+                }]
+            }),
+            exported
+        );
+        Ok(())
+    }
+
+    fn example() -> ListOfLists {
+        serde_json::from_str(
+            r#"{"title": "Food & *Drink*", "description": "What we eat", "lists": [
+                {"title": "Secret", "hidden": true, "list": ["Cake"]},
+                {"title": "Ranked", "start": 0, "list": ["Soup", {"item": "Steak", "tooltip": "Medium [rare]"}]},
+                {"title": "Shopping", "style": "checklist", "description": "This week", "list": ["Eggs"]},
+                {"title": "Snacks", "style": "inline", "list": [{"item": "Nuts", "tooltip": "Salted"}]}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_markdown_numbers_items_and_footnotes_tooltips() -> Result<()> {
+        let exported = String::from_utf8(export(ExportFormat::Markdown, "food.list", &example())?)?;
+        assert_eq!(
+            r#"# Food & \*Drink\*
+
+What we eat
+
+## Ranked
+
+0. Soup
+1. Steak[^1]
+
+## Shopping
+
+This week
+
+- [ ] Eggs
+
+## Snacks
+
+- Nuts[^2]
+
+[^1]: Medium \[rare\]
+[^2]: Salted
+"#,
+            exported
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_underlines_headings() -> Result<()> {
+        let exported = String::from_utf8(export(ExportFormat::Text, "food.list", &example())?)?;
+        assert_eq!(
+            "Food & *Drink*
+==============
+
+What we eat
+
+Ranked
+------
+
+0. Soup
+1. Steak (Medium [rare])
+
+Shopping
+--------
+
+This week
+
+[ ] Eggs
+
+Snacks
+------
+
+- Nuts (Salted)
+",
+            exported
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_names_match_site_json() {
+        for format in [
+            ExportFormat::Json,
+            ExportFormat::Markdown,
+            ExportFormat::Text,
+        ] {
+            let name = serde_json::to_value(format).unwrap();
+            assert_eq!(format, name.as_str().unwrap().parse().unwrap());
+        }
+        assert!("md".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use crate::ListOfLists;
+use crate::generator::{HISTORY_DIR, Io, publish};
+use crate::html::escape;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+pub(crate) const SITE_FEED: &str = "feed.xml";
+
+// Kept in the generator bucket rather than with the site, so it's never published.
+const SNAPSHOT: &str = "snapshot.json";
+const CHANGE_LOG: &str = "changes.json";
+
+// The change log, and so the feed, keeps only the most recent renders.
+const MAX_CHANGES: usize = 50;
+
+// Visible item text per list title, as published by a render.
+type Snapshot = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ItemChange {
+    pub list: String,
+    pub item: String,
+}
+
+// What one render added to and removed from the published page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Change {
+    pub time: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<ItemChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<ItemChange>,
+}
+
+fn snapshot(list_of_lists: &ListOfLists) -> Snapshot {
+    list_of_lists
+        .lists
+        .iter()
+        .filter(|list| !list.hidden)
+        .map(|list| {
+            let items = list.list.iter().map(|i| i.item().to_string()).collect();
+            (list.title.clone(), items)
+        })
+        .collect()
+}
+
+// Items are compared as multisets per list, so reordering isn't a change but a
+// second copy of an item (in a list allowing duplicates) is.
+fn diff(previous: &Snapshot, current: &Snapshot, time: DateTime<Utc>) -> Option<Change> {
+    let empty = Vec::new();
+    let mut titles: Vec<&String> = previous.keys().chain(current.keys()).collect();
+    titles.sort();
+    titles.dedup();
+
+    let (mut added, mut removed) = (Vec::new(), Vec::new());
+    for title in titles {
+        let before = previous.get(title).unwrap_or(&empty);
+        let after = current.get(title).unwrap_or(&empty);
+
+        let mut counts: HashMap<&str, isize> = HashMap::new();
+        for item in before {
+            *counts.entry(item).or_default() -= 1;
+        }
+        for item in after {
+            let count = counts.entry(item).or_default();
+            *count += 1;
+            if *count > 0 {
+                added.push(ItemChange {
+                    list: title.clone(),
+                    item: item.clone(),
+                });
+            }
+        }
+        for item in before {
+            let count = counts.entry(item).or_default();
+            if *count < 0 {
+                *count += 1;
+                removed.push(ItemChange {
+                    list: title.clone(),
+                    item: item.clone(),
+                });
+            }
+        }
+    }
+
+    (!added.is_empty() || !removed.is_empty()).then_some(Change {
+        time,
+        added,
+        removed,
+    })
+}
+
+pub(crate) async fn read_state<T>(io: &Io, site_url: &str, name: &str) -> Result<Option<T>>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let target = format!("{HISTORY_DIR}/{site_url}/{name}");
+    io.read_state(&target)
+        .await
+        .with_context(|| format!("read {target}"))?
+        .map(|content| serde_json::from_slice(&content).with_context(|| format!("parse {target}")))
+        .transpose()
+}
+
+pub(crate) async fn write_state<T>(io: &Io, site_url: &str, name: &str, state: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    let target = format!("{HISTORY_DIR}/{site_url}/{name}");
+    let content = serde_json::to_vec_pretty(state).with_context(|| format!("serialize {name}"))?;
+    io.write_state(&target, content)
+        .await
+        .with_context(|| format!("write {target}"))
+}
+
+// Records what this render changed since the last one and republishes the feed.
+// The first render of a site only records a baseline.
+pub(crate) async fn update(
+    io: &Io,
+    site_url: &str,
+    list_of_lists: &ListOfLists,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let current = snapshot(list_of_lists);
+    let previous: Option<Snapshot> = read_state(io, site_url, SNAPSHOT).await?;
+    let mut changes: Vec<Change> = read_state(io, site_url, CHANGE_LOG)
+        .await?
+        .unwrap_or_default();
+
+    match previous.and_then(|previous| diff(&previous, &current, now)) {
+        Some(change) => {
+            info!(
+                "{site_url}: {} item(s) added, {} removed",
+                change.added.len(),
+                change.removed.len()
+            );
+            changes.insert(0, change);
+            changes.truncate(MAX_CHANGES);
+        }
+        None => debug!("No item changes for {site_url}"),
+    }
+
+    let feed = atom(site_url, &list_of_lists.title, &changes, now);
+    publish(io, SITE_FEED, "application/atom+xml", feed.into_bytes()).await?;
+    write_state(io, site_url, CHANGE_LOG, &changes).await?;
+    // The snapshot goes last: if anything above fails, the next render retries the same diff.
+    write_state(io, site_url, SNAPSHOT, &current).await
+}
+
+fn atom(site_url: &str, title: &str, changes: &[Change], now: DateTime<Utc>) -> String {
+    let timestamp = |time: &DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let (site_url, title) = (escape(site_url), escape(title));
+    let updated = changes.first().map_or(now, |c| c.time);
+
+    let mut feed = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            "\n",
+            r#"<feed xmlns="http://www.w3.org/2005/Atom">"#,
+            "\n  <title>{title}</title>",
+            "\n  <subtitle>Items added to and removed from {title}</subtitle>",
+            "\n  <id>https://{site_url}/</id>",
+            "\n  <link rel=\"alternate\" href=\"https://{site_url}/\"/>",
+            "\n  <link rel=\"self\" href=\"https://{site_url}/{feed}\"/>",
+            "\n  <updated>{updated}</updated>",
+            "\n  <author><name>{title}</name></author>",
+        ),
+        title = title,
+        site_url = site_url,
+        feed = SITE_FEED,
+        updated = timestamp(&updated),
+    );
+
+    for change in changes {
+        let mut summary = Vec::new();
+        if !change.added.is_empty() {
+            summary.push(format!("{} added", change.added.len()));
+        }
+        if !change.removed.is_empty() {
+            summary.push(format!("{} removed", change.removed.len()));
+        }
+        let lines: String = change
+            .added
+            .iter()
+            .map(|c| ("Added", c))
+            .chain(change.removed.iter().map(|c| ("Removed", c)))
+            .map(|(verb, c)| {
+                format!(
+                    "<li>{verb} <q>{}</q> in {}</li>",
+                    escape(&c.item),
+                    escape(&c.list)
+                )
+            })
+            .collect();
+
+        feed.push_str(&format!(
+            concat!(
+                "\n  <entry>",
+                "\n    <title>{summary}</title>",
+                "\n    <id>tag:{site_url},{date}:{millis}</id>",
+                "\n    <link rel=\"alternate\" href=\"https://{site_url}/\"/>",
+                "\n    <updated>{updated}</updated>",
+                "\n    <content type=\"html\">{content}</content>",
+                "\n  </entry>",
+            ),
+            summary = summary.join(", "),
+            site_url = site_url,
+            date = change.time.format("%Y-%m-%d"),
+            millis = change.time.timestamp_millis(),
+            updated = timestamp(&change.time),
+            // type="html" content is escaped HTML, so the markup is escaped a second time.
+            content = escape(&format!("<ul>{lines}</ul>")),
+        ));
+    }
+
+    feed.push_str("\n</feed>\n");
+    feed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::List;
+    use crate::test::{site, timestamp};
+    use crate::test_util::TempDir;
+    use tokio::fs;
+
+    fn snapshot_of(lists: &[(&str, &[&str])]) -> Snapshot {
+        lists
+            .iter()
+            .map(|(title, items)| {
+                (
+                    title.to_string(),
+                    items.iter().map(|i| i.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn change(list: &str, item: &str) -> ItemChange {
+        ItemChange {
+            list: list.to_string(),
+            item: item.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_ignores_reordering() {
+        let previous = snapshot_of(&[("Letters", &["A", "B"])]);
+        let current = snapshot_of(&[("Letters", &["B", "A"])]);
+        assert_eq!(
+            None,
+            diff(&previous, &current, timestamp("2026-01-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_diff_counts_duplicates_and_lists() {
+        let previous = snapshot_of(&[("Letters", &["A", "B"]), ("Gone", &["X"])]);
+        let current = snapshot_of(&[("Letters", &["A", "C", "A"]), ("New", &["Y"])]);
+
+        let changed = diff(&previous, &current, timestamp("2026-01-01T00:00:00Z")).unwrap();
+        assert_eq!(
+            vec![
+                change("Letters", "C"),
+                change("Letters", "A"),
+                change("New", "Y")
+            ],
+            changed.added
+        );
+        assert_eq!(
+            vec![change("Gone", "X"), change("Letters", "B")],
+            changed.removed
+        );
+    }
+
+    #[test]
+    fn test_atom_escapes_content() {
+        let changes = vec![Change {
+            time: timestamp("2023-11-14T22:13:20Z"),
+            added: vec![change("Q&A", "<b>bold</b>")],
+            removed: vec![],
+        }];
+        let feed = atom(
+            "example.com",
+            "Tom & Jerry",
+            &changes,
+            timestamp("2026-01-01T00:00:00Z"),
+        );
+
+        assert!(feed.contains("<title>Tom &amp; Jerry</title>"), "{feed}");
+        assert!(feed.contains("<title>1 added</title>"), "{feed}");
+        assert!(
+            feed.contains("<updated>2023-11-14T22:13:20Z</updated>"),
+            "{feed}"
+        );
+        assert!(
+            feed.contains(
+                "&lt;q&gt;&amp;lt;b&amp;gt;bold&amp;lt;/b&amp;gt;&lt;/q&gt; in Q&amp;amp;A"
+            ),
+            "{feed}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_records_changes_after_baseline() -> Result<()> {
+        let dir = TempDir::new("feed");
+        let io = dir.io();
+        let letters = |items: &[&str]| site(vec![List::new("Letters", false, false, items)]);
+
+        update(
+            &io,
+            "example.com",
+            &letters(&["A"]),
+            timestamp("2026-01-01T00:00:00Z"),
+        )
+        .await?;
+        let feed = fs::read_to_string(dir.join("site").join(SITE_FEED)).await?;
+        assert!(!feed.contains("<entry>"), "{feed}");
+
+        let mut hidden = letters(&["A", "B"]);
+        hidden.lists.push(List::new("Hidden", true, false, &["Z"]));
+        update(
+            &io,
+            "example.com",
+            &hidden,
+            timestamp("2026-01-01T00:01:00Z"),
+        )
+        .await?;
+        update(
+            &io,
+            "example.com",
+            &hidden,
+            timestamp("2026-01-01T00:02:00Z"),
+        )
+        .await?;
+
+        let changes: Vec<Change> = read_state(&io, "example.com", CHANGE_LOG).await?.unwrap();
+        assert_eq!(1, changes.len());
+        assert_eq!(timestamp("2026-01-01T00:01:00Z"), changes[0].time);
+        assert_eq!(vec![change("Letters", "B")], changes[0].added);
+
+        let feed = fs::read_to_string(dir.join("site").join(SITE_FEED)).await?;
+        assert_eq!(1, feed.matches("<entry>").count(), "{feed}");
+        assert!(!feed.contains("Hidden"), "{feed}");
+        assert!(!dir.join("site").join(HISTORY_DIR).exists());
+        Ok(())
+    }
+}
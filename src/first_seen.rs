@@ -0,0 +1,197 @@
+use crate::ListOfLists;
+use crate::feed::{read_state, write_state};
+use crate::generator::Io;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+// When each item was first rendered, by list title and item text. Kept with the
+// change feed's state.
+const FIRST_SEEN: &str = "first-seen.json";
+
+// Items are "new" for this many days unless a site sets new_item_days.
+const DEFAULT_NEW_ITEM_DAYS: u32 = 7;
+
+// Items already on the page when the index was created have no date (null), so a
+// site's first render doesn't badge everything.
+pub(crate) type FirstSeen = BTreeMap<String, BTreeMap<String, Option<DateTime<Utc>>>>;
+
+pub(crate) async fn read(io: &Io, site_url: &str) -> Result<Option<FirstSeen>> {
+    read_state(io, site_url, FIRST_SEEN).await
+}
+
+pub(crate) async fn write(io: &Io, site_url: &str, first_seen: &FirstSeen) -> Result<()> {
+    write_state(io, site_url, FIRST_SEEN, first_seen).await
+}
+
+// Carries dates over for items still on the page, wherever they've moved to, and
+// dates new ones `now`. Items no longer on the page are dropped, so one that's
+// removed and later re-added is new again.
+pub(crate) fn update(
+    previous: Option<&FirstSeen>,
+    list_of_lists: &ListOfLists,
+    now: DateTime<Utc>,
+) -> FirstSeen {
+    let mut first_seen = FirstSeen::new();
+    for list in &list_of_lists.lists {
+        let before = previous.and_then(|previous| previous.get(&list.title));
+        let seen = first_seen.entry(list.title.clone()).or_default();
+        for item in &list.list {
+            let date = match (previous, before.and_then(|b| b.get(item.item()))) {
+                (_, Some(date)) => *date,
+                (None, None) => None,
+                (Some(_), None) => Some(now),
+            };
+            seen.insert(item.item().to_string(), date);
+        }
+    }
+    first_seen
+}
+
+// `first_seen` rekeyed by a translation's list titles and item text, so its page can
+// badge the same items. `site` and `translated` are item for item, as translated()
+// leaves them before visible_at() drops anything.
+pub(crate) fn translate(
+    first_seen: &FirstSeen,
+    site: &ListOfLists,
+    translated: &ListOfLists,
+) -> FirstSeen {
+    let mut translated_seen = FirstSeen::new();
+    for (list, translated_list) in site.lists.iter().zip(&translated.lists) {
+        let Some(seen) = first_seen.get(&list.title) else {
+            continue;
+        };
+        let translated_seen = translated_seen
+            .entry(translated_list.title.clone())
+            .or_default();
+        for (item, translated_item) in list.list.iter().zip(&translated_list.list) {
+            if let Some(date) = seen.get(item.item()) {
+                translated_seen.insert(translated_item.item().to_string(), *date);
+            }
+        }
+    }
+    translated_seen
+}
+
+fn window(list_of_lists: &ListOfLists) -> Duration {
+    Duration::days(
+        list_of_lists
+            .new_item_days
+            .unwrap_or(DEFAULT_NEW_ITEM_DAYS)
+            .into(),
+    )
+}
+
+// Item text per list title for items first seen within the site's window of `now`.
+pub(crate) fn new_items(
+    first_seen: &FirstSeen,
+    list_of_lists: &ListOfLists,
+    now: DateTime<Utc>,
+) -> BTreeMap<String, Vec<String>> {
+    let since = now - window(list_of_lists);
+    first_seen
+        .iter()
+        .map(|(title, items)| {
+            let new = items
+                .iter()
+                .filter(|(_, date)| date.is_some_and(|date| since < date))
+                .map(|(item, _)| item.clone())
+                .collect();
+            (title.clone(), new)
+        })
+        .collect()
+}
+
+// When each dated item's "new" badge lapses; a render is stale once one has passed.
+pub(crate) fn expiries<'a>(
+    first_seen: &'a FirstSeen,
+    list_of_lists: &ListOfLists,
+) -> impl Iterator<Item = DateTime<Utc>> + 'a {
+    let window = window(list_of_lists);
+    first_seen
+        .values()
+        .flat_map(BTreeMap::values)
+        .flatten()
+        .map(move |date| *date + window)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::List;
+    use crate::test::{site, timestamp};
+
+    #[test]
+    fn test_update_survives_reordering() {
+        let first = site(vec![List::new("Letters", false, false, &["A", "B"])]);
+        let baseline = update(None, &first, timestamp("2026-01-01T00:00:00Z"));
+        assert_eq!(None, baseline["Letters"]["A"]);
+
+        let second = site(vec![List::new("Letters", false, false, &["C", "B", "A"])]);
+        let added = timestamp("2026-01-02T00:00:00Z");
+        let first_seen = update(Some(&baseline), &second, added);
+        assert_eq!(None, first_seen["Letters"]["A"]);
+        assert_eq!(None, first_seen["Letters"]["B"]);
+        assert_eq!(Some(added), first_seen["Letters"]["C"]);
+
+        let third = site(vec![List::new("Letters", false, false, &["B", "C"])]);
+        let first_seen = update(Some(&first_seen), &third, timestamp("2026-01-03T00:00:00Z"));
+        assert_eq!(Some(added), first_seen["Letters"]["C"]);
+        assert!(!first_seen["Letters"].contains_key("A"));
+    }
+
+    #[test]
+    fn test_new_items_within_window() {
+        let mut list_of_lists = site(vec![List::new("Letters", false, false, &["A", "B"])]);
+        let first_seen = FirstSeen::from([(
+            "Letters".to_string(),
+            BTreeMap::from([
+                ("A".to_string(), Some(timestamp("2026-01-01T00:00:00Z"))),
+                ("B".to_string(), Some(timestamp("2026-01-05T00:00:00Z"))),
+                ("C".to_string(), None),
+            ]),
+        )]);
+
+        let now = timestamp("2026-01-09T00:00:00Z");
+        assert_eq!(
+            vec!["B".to_string()],
+            new_items(&first_seen, &list_of_lists, now)["Letters"]
+        );
+
+        list_of_lists.new_item_days = Some(0);
+        assert!(new_items(&first_seen, &list_of_lists, now)["Letters"].is_empty());
+
+        list_of_lists.new_item_days = Some(30);
+        assert_eq!(
+            vec![
+                timestamp("2026-01-31T00:00:00Z"),
+                timestamp("2026-02-04T00:00:00Z")
+            ],
+            expiries(&first_seen, &list_of_lists).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_translate_follows_items() {
+        let mut letters = List::new("Letters", false, false, &["A", "B"]);
+        letters.translations = BTreeMap::from([(
+            "es".to_string(),
+            serde_json::from_str(r#"{"title": "Letras", "list": ["Á", "Bé"]}"#).unwrap(),
+        )]);
+        let site = site(vec![letters]);
+        let added = timestamp("2026-01-02T00:00:00Z");
+        let first_seen = FirstSeen::from([(
+            "Letters".to_string(),
+            BTreeMap::from([("A".to_string(), None), ("B".to_string(), Some(added))]),
+        )]);
+
+        let translated = site.clone().translated("es");
+        assert_eq!(
+            FirstSeen::from([(
+                "Letras".to_string(),
+                BTreeMap::from([("Á".to_string(), None), ("Bé".to_string(), Some(added))]),
+            )]),
+            translate(&first_seen, &site, &translated)
+        );
+    }
+}
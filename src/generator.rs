@@ -1,9 +1,27 @@
-use crate::{ListOfLists, s3util};
-use anyhow::{Context, Result};
-use log::{debug, trace};
+use crate::assets::{self, ASSETS_DIR};
+use crate::csp::ContentSecurityPolicy;
+use crate::export::export;
+use crate::feed;
+use crate::first_seen::{self, FirstSeen};
+use crate::imports::ImportPolicy;
+use crate::include;
+use crate::locale;
+use crate::s3util::ObjectHeaders;
+use crate::theme::{self, ThemeContext};
+use crate::{ExportFormat, ListItem, ListOfLists, s3util, tags_of};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::{debug, info, trace, warn};
 use minify_html::Cfg;
-use minijinja::{Environment, Error, State, Value, context};
+use minijinja::value::ViaDeserialize;
+use minijinja::{Environment, Error, State, Template, Value, context};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
 use std::sync::LazyLock;
 use std::{
     path::{Path, PathBuf},
@@ -12,10 +30,26 @@ use std::{
 use tokio::fs;
 
 pub const SITE_INDEX_TEMPLATE: &str = "index.template";
+// Pinned copies of CDN files in the generator bucket, laid out as <host>/<path>.
+pub const VENDOR_DIR: &str = "vendor";
+// Per-site state kept between renders in the generator bucket, as history/<site_url>/<file>.
+pub const HISTORY_DIR: &str = "history";
 const SITE_INDEX: &str = "index.html";
+const SITE_CSP: &str = "csp.json";
+// The site's last successful render, kept with its other state.
+const LAST_RENDER: &str = "last-render.json";
 
 const DIV_ID_SAFE: &str = "div_id_safe";
 const OPTIMIZE_IMPORT: &str = "optimize_import";
+const LIST_TAGS: &str = "list_tags";
+
+// Pages are invalidated on every publish, so browsers only need to revalidate occasionally.
+const PAGE_CACHE_CONTROL: &str = "public, max-age=300";
+// Asset names change with their content, so they can be cached forever.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+const MANIFEST_CACHE_CONTROL: &str = "no-cache";
+// x-amz-meta-content-sha256: hex SHA-256 of the unencoded content.
+const CONTENT_HASH_METADATA: &str = "content-sha256";
 
 pub enum Io {
     S3 {
@@ -56,43 +90,134 @@ impl Io {
             .with_context(|| format!("read {SITE_INDEX_TEMPLATE}"))
     }
 
-    async fn read(&self, target: &str) -> Result<String> {
+    pub(crate) async fn read(&self, target: &str) -> Result<String> {
+        let bytes = self.read_bytes(target).await?;
+        String::from_utf8(bytes).with_context(|| format!("{target} is not UTF-8"))
+    }
+
+    pub(crate) async fn read_bytes(&self, target: &str) -> Result<Vec<u8>> {
         match self {
             Io::S3 {
                 s3_client,
                 generator_bucket,
                 ..
-            } => {
-                let bytes = s3util::get(s3_client, generator_bucket, target).await?;
-                let s = str::from_utf8(&bytes)
-                    .with_context(|| format!("{target} from {generator_bucket} is not UTF-8"))?;
-                Ok(s.into())
-            }
+            } => Ok(s3util::get(s3_client, generator_bucket, target)
+                .await?
+                .to_vec()),
 
             Io::LocalFile { generator_path, .. } => {
                 let path = generator_path.join(target);
                 debug!("Reading {path:?}");
-                fs::read_to_string(&path)
+                fs::read(&path)
                     .await
                     .with_context(|| format!("read {path:?}"))
             }
         }
     }
 
-    async fn write(&self, target: &str, content: Vec<u8>) -> Result<()> {
+    // Generator state such as the change feed's snapshot lives in the generator
+    // bucket, which no distribution serves.
+    pub(crate) async fn read_state(&self, target: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Io::S3 {
+                s3_client,
+                generator_bucket,
+                ..
+            } => Ok(s3util::get_if_exists(s3_client, generator_bucket, target)
+                .await?
+                .map(|bytes| bytes.to_vec())),
+
+            Io::LocalFile { generator_path, .. } => {
+                read_if_exists(&generator_path.join(target)).await
+            }
+        }
+    }
+
+    pub(crate) async fn write_state(&self, target: &str, content: Vec<u8>) -> Result<()> {
+        match self {
+            Io::S3 {
+                s3_client,
+                generator_bucket,
+                ..
+            } => {
+                s3util::put(
+                    s3_client,
+                    generator_bucket,
+                    target,
+                    "application/json",
+                    &ObjectHeaders::default(),
+                    content,
+                )
+                .await
+            }
+
+            Io::LocalFile { generator_path, .. } => {
+                let path = generator_path.join(target);
+                debug!("Writing to {path:?}");
+                let dir = path.parent().unwrap_or(generator_path);
+                fs::create_dir_all(dir)
+                    .await
+                    .with_context(|| format!("create dir {dir:?}"))?;
+                fs::write(&path, content)
+                    .await
+                    .with_context(|| format!("write {path:?}"))
+            }
+        }
+    }
+
+    // Deleting an object that doesn't exist isn't an error, as in S3.
+    async fn delete(&self, target: &str) -> Result<()> {
+        match self {
+            Io::S3 {
+                s3_client,
+                site_bucket,
+                ..
+            } => s3util::delete(s3_client, site_bucket, target).await,
+
+            Io::LocalFile { site_path, .. } => {
+                let path = site_path.join(target);
+                debug!("Deleting {path:?}");
+                match fs::remove_file(&path).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        Err(e).with_context(|| format!("delete {path:?}"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    async fn write(
+        &self,
+        target: &str,
+        content_type: &str,
+        headers: &ObjectHeaders,
+        content: Vec<u8>,
+    ) -> Result<()> {
         match self {
             Io::S3 {
                 s3_client,
                 site_bucket,
                 ..
-            } => s3util::put(s3_client, site_bucket, target, "text/html", content).await,
+            } => {
+                s3util::put(
+                    s3_client,
+                    site_bucket,
+                    target,
+                    content_type,
+                    headers,
+                    content,
+                )
+                .await
+            }
 
             Io::LocalFile { site_path, .. } => {
                 let path = site_path.join(target);
                 debug!("Writing to {path:?}");
-                fs::create_dir_all(site_path)
+                let dir = path.parent().unwrap_or(site_path);
+                fs::create_dir_all(dir)
                     .await
-                    .with_context(|| format!("create dir {site_path:?}"))?;
+                    .with_context(|| format!("create dir {dir:?}"))?;
                 fs::write(&path, content)
                     .await
                     .with_context(|| format!("write {path:?}"))
@@ -101,18 +226,59 @@ impl Io {
     }
 }
 
-async fn read_list(io: &Io, site_url: &str) -> Result<ListOfLists> {
+async fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    debug!("Reading {path:?} if it exists");
+    match fs::read(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("read {path:?}")),
+    }
+}
+
+// The validated site, with references resolved for rendering, and its validation warnings.
+async fn read_list(io: &Io, site_url: &str) -> Result<(ListOfLists, Vec<String>)> {
     let key = format!("{site_url}.json");
     let content = io.read(&key).await.with_context(|| format!("read {key}"))?;
-    let list_of_lists: ListOfLists = serde_json::from_str(content.as_str())
-        .with_context(|| format!("parse {key} as ListOfLists"))?;
+    let (site, warnings) = parse_site(io, &key, &content).await?;
+    Ok((site.resolved()?, warnings))
+}
+
+// Parses and validates site JSON, splicing in the lists it includes.
+async fn parse_site(io: &Io, key: &str, content: &str) -> Result<(ListOfLists, Vec<String>)> {
+    let mut site: serde_json::Value =
+        serde_json::from_str(content).with_context(|| format!("parse {key}"))?;
+    include::expand(io, key, &mut site).await?;
+    let list_of_lists: ListOfLists =
+        serde_json::from_value(site).with_context(|| format!("parse {key} as ListOfLists"))?;
     trace!("{list_of_lists:?}");
 
     list_of_lists
-        .validate()
+        .validate_with_warnings()
         .with_context(|| format!("validate {key}"))
 }
 
+// Validates site JSON that hasn't been written to `key` yet, e.g. a rewritten local file.
+pub async fn validate_site_json(io: &Io, key: &str, content: &str) -> Result<()> {
+    parse_site(io, key, content).await.map(|_| ())
+}
+
+// Generator bucket keys a site includes, lists or fragments, directly or via other
+// includes, and its theme's custom CSS.
+pub async fn site_includes(io: &Io, site_url: &str) -> Result<BTreeSet<String>> {
+    let key = format!("{site_url}.json");
+    let content = io.read(&key).await.with_context(|| format!("read {key}"))?;
+    let mut site = serde_json::from_str(&content).with_context(|| format!("parse {key}"))?;
+    let mut included = include::expand(io, &key, &mut site).await?;
+    if let Some(custom_css) = site
+        .get("theme")
+        .and_then(|theme| theme.get("custom_css"))
+        .and_then(serde_json::Value::as_str)
+    {
+        included.insert(custom_css.to_string());
+    }
+    Ok(included)
+}
+
 fn div_id_safe(_: &State, value: String) -> Result<String, Error> {
     Ok(sanitized_div_id(value))
 }
@@ -127,6 +293,10 @@ where
         .into_owned()
 }
 
+fn list_tags(_: &State, items: ViaDeserialize<Vec<ListItem>>) -> Result<Vec<String>, Error> {
+    Ok(tags_of(&items).into_iter().map(String::from).collect())
+}
+
 fn optimize_import(_: &State, value: String) -> Result<String, Error> {
     Ok(inner_optimize_import(value))
 }
@@ -178,39 +348,324 @@ pub fn build_environment(template: &str) -> Result<Environment<'_>> {
         .context("compile index template")?;
     env.add_filter(DIV_ID_SAFE, div_id_safe);
     env.add_filter(OPTIMIZE_IMPORT, optimize_import);
+    env.add_filter(LIST_TAGS, list_tags);
     Ok(env)
 }
 
+#[derive(Serialize, Deserialize)]
+struct LastRender {
+    // The `now` it was rendered at.
+    time: DateTime<Utc>,
+    pages: Vec<String>,
+}
+
+// True if the site's last successful render is stale by `until`: a publish_at,
+// expire_at or badge expiry falls after it, or there's no render on record.
+pub async fn visibility_changed(io: &Io, site_url: &str, until: DateTime<Utc>) -> Result<bool> {
+    let Some(LastRender { time: rendered, .. }) =
+        feed::read_state(io, site_url, LAST_RENDER).await?
+    else {
+        return Ok(true);
+    };
+    let (list_of_lists, _) = read_list(io, site_url).await?;
+    let first_seen = first_seen::read(io, site_url)
+        .await
+        .with_context(|| format!("read first-seen index for {site_url}"))?
+        .unwrap_or_default();
+    Ok(list_of_lists
+        .visibility_boundaries()
+        .chain(first_seen::expiries(&first_seen, &list_of_lists))
+        .any(|boundary| rendered < boundary && boundary <= until))
+}
+
+// A site's visible lists as of `now` in an export format, without publishing anything.
+pub async fn export_site(
+    io: &Io,
+    site_url: &str,
+    format: ExportFormat,
+    now: DateTime<Utc>,
+) -> Result<Vec<u8>> {
+    let (list_of_lists, _) = read_list(io, site_url).await?;
+    let list_of_lists = list_of_lists
+        .visible_at(now)
+        .with_context(|| format!("export {} for {site_url}", format.file_name()))?
+        .sorted(now);
+    export(format, site_url, &list_of_lists)
+        .with_context(|| format!("export {} for {site_url}", format.file_name()))
+}
+
+// How a render builds its pages and treats the site's warnings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    pub minify: bool,
+    // Serve the template's CDN scripts and stylesheets from vendored copies.
+    pub vendor_assets: bool,
+    // Fail rather than render if the site has validation warnings.
+    pub strict: bool,
+}
+
+// `now` is the instant publish_at/expire_at are evaluated against; callers pass
+// the wall clock outside of tests and previews. Returns the pages published (index.html
+// and <lang>/index.html for each translation) and removed, for invalidation.
 pub async fn render_site(
     io: &Io,
     env: &Environment<'_>,
     site_url: &str,
-    minify: bool,
-) -> Result<()> {
-    let list_of_lists = read_list(io, site_url).await?;
+    now: DateTime<Utc>,
+    import_policy: &ImportPolicy,
+    options: RenderOptions,
+) -> Result<Vec<String>> {
+    let (site, warnings) = read_list(io, site_url).await?;
+    if options.strict && !warnings.is_empty() {
+        bail!(
+            "{site_url} has {} warning(s):\n{}",
+            warnings.len(),
+            warnings.join("\n")
+        );
+    }
+    for warning in warnings {
+        warn!("{site_url}: {warning}");
+    }
+    let list_of_lists = site
+        .clone()
+        .visible_at(now)
+        .with_context(|| format!("render {site_url}"))?
+        .sorted(now);
+    if let Some(footer) = &list_of_lists.footer {
+        import_policy
+            .check(&footer.imports)
+            .with_context(|| format!("check imports for {site_url}"))?;
+    }
+
+    let previous = first_seen::read(io, site_url)
+        .await
+        .with_context(|| format!("read first-seen index for {site_url}"))?;
+    let first_seen = first_seen::update(previous.as_ref(), &list_of_lists, now);
+    let new_items = first_seen::new_items(&first_seen, &list_of_lists, now);
 
     let template = env
         .get_template(SITE_INDEX)
         .context("get compiled index template")?;
+    let alternates = alternates(&site);
+    let custom_css = theme::read_custom_css(io, site.theme.as_ref())
+        .await
+        .with_context(|| format!("read theme for {site_url}"))?;
+    let theme = theme::context(site.theme.as_ref(), custom_css);
+
+    let page = Page {
+        path: "",
+        list_of_lists: &list_of_lists,
+        first_seen: &first_seen,
+        new_items: &new_items,
+        theme: &theme,
+    };
+    let mut csp = render_page(
+        io,
+        &template,
+        site_url,
+        &page,
+        &alternates,
+        options.minify,
+        options.vendor_assets,
+    )
+    .await?;
+    let mut pages = vec![SITE_INDEX.to_string()];
+
+    // Translations are item for item with the site until visible_at() drops items,
+    // which is when first-seen dates can follow items to their translated text.
+    for lang in site.translations.keys() {
+        let translated = site.clone().translated(lang);
+        let translated_first_seen = first_seen::translate(&first_seen, &site, &translated);
+        let translated = translated
+            .visible_at(now)
+            .with_context(|| format!("render the {lang:?} translation of {site_url}"))?
+            .sorted(now);
+        let translated_new_items = first_seen::new_items(&translated_first_seen, &translated, now);
+        let path = format!("{lang}/");
+        let page = Page {
+            path: &path,
+            list_of_lists: &translated,
+            first_seen: &translated_first_seen,
+            new_items: &translated_new_items,
+            theme: &theme,
+        };
+        // csp.json lists what each page's own policy allows.
+        csp.merge(
+            &render_page(
+                io,
+                &template,
+                site_url,
+                &page,
+                &alternates,
+                options.minify,
+                options.vendor_assets,
+            )
+            .await?,
+        );
+        pages.push(format!("{path}{SITE_INDEX}"));
+    }
+
+    let manifest = serde_json::to_vec_pretty(&csp.manifest()).context("serialize CSP")?;
+    publish(io, SITE_CSP, "application/json", manifest)
+        .await
+        .with_context(|| format!("write {SITE_CSP} for {site_url}"))?;
+
+    for format in &list_of_lists.exports {
+        let target = format.file_name();
+        debug!("Publishing {target} for {site_url}");
+        let content = export(*format, site_url, &list_of_lists)
+            .with_context(|| format!("export {target} for {site_url}"))?;
+        publish(io, target, format.content_type(), content)
+            .await
+            .with_context(|| format!("write {target} for {site_url}"))?;
+    }
+
+    first_seen::write(io, site_url, &first_seen)
+        .await
+        .with_context(|| format!("write first-seen index for {site_url}"))?;
+
+    feed::update(io, site_url, &list_of_lists, now)
+        .await
+        .with_context(|| format!("update change feed for {site_url}"))?;
+
+    // A language dropped from translations would otherwise leave its page up, still
+    // claiming to be canonical.
+    let previous: Option<LastRender> = feed::read_state(io, site_url, LAST_RENDER).await?;
+    let removed: Vec<String> = previous
+        .map(|previous| previous.pages)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|page| !pages.contains(page))
+        .collect();
+    for page in &removed {
+        info!("Removing {page} from {site_url}");
+        unpublish(io, page)
+            .await
+            .with_context(|| format!("remove {page} from {site_url}"))?;
+    }
+
+    // Last, so the next scheduled run retries a render that failed part way.
+    let last_render = LastRender {
+        time: now,
+        pages: pages.clone(),
+    };
+    feed::write_state(io, site_url, LAST_RENDER, &last_render).await?;
+
+    pages.extend(removed);
+    Ok(pages)
+}
+
+// What differs between the pages of a site's languages.
+struct Page<'a> {
+    // Relative to the site root: "" for the site's own language, "<lang>/" for a translation.
+    path: &'a str,
+    list_of_lists: &'a ListOfLists,
+    first_seen: &'a FirstSeen,
+    new_items: &'a BTreeMap<String, Vec<String>>,
+    theme: &'a ThemeContext,
+}
+
+// A link to one of a multilingual site's pages.
+#[derive(Serialize)]
+struct Alternate {
+    lang: &'static str,
+    name: &'static str,
+    path: String,
+}
+
+// The site's own language, then its translations; none for a single-language site.
+fn alternates(site: &ListOfLists) -> Vec<Alternate> {
+    if site.translations.is_empty() {
+        return Vec::new();
+    }
+    let own = locale::for_site(site);
+    let mut alternates = vec![Alternate {
+        lang: own.code,
+        name: own.name,
+        path: String::new(),
+    }];
+    // Validation has rejected unsupported languages.
+    for locale in site
+        .translations
+        .keys()
+        .filter_map(|lang| locale::find(lang))
+    {
+        alternates.push(Alternate {
+            lang: locale.code,
+            name: locale.name,
+            path: format!("{}/", locale.code),
+        });
+    }
+    alternates
+}
+
+// Renders, vendors, minifies and publishes one language's page, returning the
+// Content-Security-Policy it carries.
+async fn render_page(
+    io: &Io,
+    template: &Template<'_, '_>,
+    site_url: &str,
+    page: &Page<'_>,
+    alternates: &[Alternate],
+    minify: bool,
+    vendor_assets: bool,
+) -> Result<ContentSecurityPolicy> {
+    let target = format!("{}{SITE_INDEX}", page.path);
+    // Relative URLs in the template and vendored assets resolve from the site root.
+    let root = if page.path.is_empty() { "" } else { "../" };
 
-    debug!("Rendering {SITE_INDEX} for {site_url}");
+    // The page's language, text direction and the template's translated text.
+    let locale = locale::for_site(page.list_of_lists);
+
+    debug!("Rendering {target} for {site_url}");
     let site = template
-        .render(context! { site_url, ..Value::from_serialize(&list_of_lists) })
-        .with_context(|| format!("render {SITE_INDEX} for {site_url}"))?;
-    debug!("Rendered {SITE_INDEX} for {site_url}");
+        .render(context! {
+            site_url,
+            vendor_assets,
+            first_seen => page.first_seen,
+            new_items => page.new_items,
+            lang => locale.code,
+            dir => locale.dir(),
+            ui => Value::from_serialize(&locale.ui),
+            path => page.path,
+            root,
+            alternates,
+            theme => Value::from_serialize(page.theme),
+            ..Value::from_serialize(page.list_of_lists)
+        })
+        .with_context(|| format!("render {target} for {site_url}"))?;
+    debug!("Rendered {target} for {site_url}");
+
+    let site = if vendor_assets {
+        let (site, assets) = assets::vendor(io, &site, root)
+            .await
+            .with_context(|| format!("vendor assets for {site_url}"))?;
+        // Assets go first so the published page never references a missing file.
+        for asset in assets {
+            debug!("Publishing {} for {site_url}", asset.key);
+            publish(io, &asset.key, asset.content_type, asset.content)
+                .await
+                .with_context(|| format!("write {} for {site_url}", asset.key))?;
+        }
+        site
+    } else {
+        site
+    };
 
     let site = if minify {
         let original_size = site.len();
-        debug!("Minifying {SITE_INDEX} for {site_url} (original size: {original_size})");
+        debug!("Minifying {target} for {site_url} (original size: {original_size})");
 
         let mut cfg = Cfg::new();
         cfg.minify_css = true;
         cfg.minify_js = true;
+        // The CSP <meta> is inserted after <head>, so it has to survive minification.
+        cfg.keep_html_and_head_opening_tags = true;
 
         let site = minify_html::minify(site.as_bytes(), &cfg);
 
         debug!(
-            "Minified {SITE_INDEX} for {site_url}: {:.1}% (new size: {})",
+            "Minified {target} for {site_url}: {:.1}% (new size: {})",
             100.0 * (site.len() as f64 / original_size as f64),
             site.len()
         );
@@ -220,26 +675,197 @@ pub async fn render_site(
         site.as_bytes().to_vec()
     };
 
-    io.write(SITE_INDEX, site)
+    // Hashes must cover the final bytes, so the policy is computed after minification.
+    let csp = ContentSecurityPolicy::from_html(
+        str::from_utf8(&site).context("rendered site is not UTF-8")?,
+        &page.list_of_lists.csp_sources,
+    );
+    let site = insert_csp_meta(site, &csp).with_context(|| format!("add CSP for {site_url}"))?;
+
+    publish(io, &target, "text/html", site)
+        .await
+        .with_context(|| format!("write {target} for {site_url}"))?;
+    Ok(csp)
+}
+
+// Writes an object with its cache policy and content hash, plus brotli and gzip
+// variants (`<target>.br`, `<target>.gz`) when it has any. The variants go
+// first, so the identity object is only updated once they're in place.
+pub(crate) async fn publish(
+    io: &Io,
+    target: &str,
+    content_type: &str,
+    content: Vec<u8>,
+) -> Result<()> {
+    let hash: String = Sha256::digest(&content)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let headers = |content_encoding: Option<&str>| ObjectHeaders {
+        cache_control: Some(cache_control(target).to_string()),
+        content_encoding: content_encoding.map(String::from),
+        metadata: HashMap::from([(CONTENT_HASH_METADATA.to_string(), hash.clone())]),
+    };
+
+    if has_encoded_variants(target) {
+        for (encoding, extension, encoded) in [
+            ("br", "br", brotli_encode(&content)?),
+            ("gzip", "gz", gzip_encode(&content)?),
+        ] {
+            let variant = format!("{target}.{extension}");
+            debug!(
+                "Writing {variant} ({:.1}% of {target})",
+                100.0 * (encoded.len() as f64 / content.len().max(1) as f64)
+            );
+            io.write(&variant, content_type, &headers(Some(encoding)), encoded)
+                .await?;
+        }
+    }
+    io.write(target, content_type, &headers(None), content)
         .await
-        .with_context(|| format!("write {SITE_INDEX} for {site_url}"))
+}
+
+// Deletes an object published by publish(), variants first so they're never
+// served in place of a page that's gone.
+async fn unpublish(io: &Io, target: &str) -> Result<()> {
+    if has_encoded_variants(target) {
+        for extension in ["br", "gz"] {
+            io.delete(&format!("{target}.{extension}")).await?;
+        }
+    }
+    io.delete(target).await
+}
+
+fn cache_control(target: &str) -> &'static str {
+    if target.starts_with(&format!("{ASSETS_DIR}/")) {
+        IMMUTABLE_CACHE_CONTROL
+    } else if target == SITE_CSP {
+        MANIFEST_CACHE_CONTROL
+    } else {
+        PAGE_CACHE_CONTROL
+    }
+}
+
+// Must match the paths the viewer-request function in site-module/main.tf rewrites
+// to a variant, or CloudFront would request variants that don't exist.
+fn has_encoded_variants(target: &str) -> bool {
+    static ENCODED_PAGE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(?:[a-z]{2}/)?index\.html$").unwrap());
+    static ENCODED_ASSET: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^assets/[^/]+\.(?:css|js|svg)$").unwrap());
+
+    ENCODED_PAGE.is_match(target) || ENCODED_ASSET.is_match(target)
+}
+
+fn brotli_encode(content: &[u8]) -> Result<Vec<u8>> {
+    // Quality 11 and a 4 MiB window: the slowest, smallest setting, which is fine at publish time.
+    let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 11, 22);
+    encoder.write_all(content).context("brotli encode")?;
+    // into_inner finishes the stream.
+    Ok(encoder.into_inner())
+}
+
+fn gzip_encode(content: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(content).context("gzip encode")?;
+    encoder.finish().context("gzip encode")
+}
+
+// The policy only governs content after it, so it goes first in <head>, but after
+// <meta charset>: a long policy could push that past the 1024 bytes browsers scan
+// for it.
+fn insert_csp_meta(site: Vec<u8>, csp: &ContentSecurityPolicy) -> Result<Vec<u8>> {
+    static HEAD_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<head\b[^>]*>").unwrap());
+    static CHARSET_TAG: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)\A\s*<meta\s+charset\b[^>]*>").unwrap());
+
+    let site = String::from_utf8(site).context("rendered site is not UTF-8")?;
+    let mut head_end = HEAD_TAG
+        .find(&site)
+        .context("rendered site has no <head> tag")?
+        .end();
+    if let Some(charset) = CHARSET_TAG.find(&site[head_end..]) {
+        head_end += charset.end();
+    }
+    let meta = format!(
+        r#"<meta http-equiv="Content-Security-Policy" content="{}">"#,
+        csp.meta_value()
+    );
+    Ok([&site[..head_end], &meta, &site[head_end..]]
+        .concat()
+        .into_bytes())
 }
 
 pub async fn update_site(
     site_url: String,
     generator_bucket: String,
     s3_client: Option<aws_sdk_s3::Client>,
-    minify: bool,
+    now: DateTime<Utc>,
+    import_policy: &ImportPolicy,
+    options: RenderOptions,
 ) -> Result<()> {
     let io = Io::new(site_url.clone(), generator_bucket, s3_client);
     let template = io.read_template().await?;
     let env = build_environment(&template)?;
-    render_site(&io, &env, &site_url, minify).await
+    render_site(&io, &env, &site_url, now, import_policy, options).await?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_cache_control_by_target() {
+        assert_eq!(PAGE_CACHE_CONTROL, cache_control(SITE_INDEX));
+        assert_eq!(MANIFEST_CACHE_CONTROL, cache_control(SITE_CSP));
+        assert_eq!(PAGE_CACHE_CONTROL, cache_control("feed.xml"));
+        assert_eq!(
+            IMMUTABLE_CACHE_CONTROL,
+            cache_control("assets/bootstrap.min.0123456789abcdef.css")
+        );
+    }
+
+    #[test]
+    fn test_has_encoded_variants() {
+        assert!(has_encoded_variants(SITE_INDEX));
+        assert!(has_encoded_variants("es/index.html"));
+        assert!(!has_encoded_variants("history/index.html"));
+        assert!(has_encoded_variants("assets/a.0123.css"));
+        assert!(has_encoded_variants("assets/a.0123.js"));
+        assert!(!has_encoded_variants("assets/a.0123.woff2"));
+        assert!(!has_encoded_variants(SITE_CSP));
+        assert!(!has_encoded_variants("images/a.js"));
+    }
+
+    #[tokio::test]
+    async fn test_publish_writes_encoded_variants() -> Result<()> {
+        use std::io::Read;
+
+        let dir = TempDir::new("publish");
+        let io = dir.io();
+        let page = "<!doctype html><html><head></head><body>hello</body></html>".repeat(10);
+
+        publish(&io, SITE_INDEX, "text/html", page.clone().into_bytes()).await?;
+        publish(&io, SITE_CSP, "application/json", b"{}".to_vec()).await?;
+
+        let site = dir.join("site");
+        assert_eq!(page, fs::read_to_string(site.join(SITE_INDEX)).await?);
+
+        let mut decoded = String::new();
+        brotli::Decompressor::new(&fs::read(site.join("index.html.br")).await?[..], 4096)
+            .read_to_string(&mut decoded)?;
+        assert_eq!(page, decoded);
+
+        decoded.clear();
+        flate2::read::GzDecoder::new(&fs::read(site.join("index.html.gz")).await?[..])
+            .read_to_string(&mut decoded)?;
+        assert_eq!(page, decoded);
+
+        assert!(!site.join("csp.json.br").exists());
+        Ok(())
+    }
 
     #[test]
     fn test_div_id_safe() {
@@ -337,11 +963,23 @@ mod test {
     "#;
 
     fn render_index_template(list_of_lists: &ListOfLists) -> String {
+        let locale = locale::for_site(list_of_lists);
+        let alternates = alternates(list_of_lists);
         let template = include_str!("../index.template");
         let env = build_environment(template).expect("index.template must compile");
         env.get_template(SITE_INDEX)
             .expect("compiled template must be registered")
-            .render(context! { site_url => "example.com", ..Value::from_serialize(list_of_lists) })
+            .render(context! {
+                site_url => "example.com",
+                lang => locale.code,
+                dir => locale.dir(),
+                ui => Value::from_serialize(&locale.ui),
+                path => "",
+                root => "",
+                alternates,
+                theme => Value::from_serialize(theme::context(list_of_lists.theme.as_ref(), None)),
+                ..Value::from_serialize(list_of_lists)
+            })
             .expect("index.template must render")
     }
 
@@ -362,6 +1000,349 @@ mod test {
         );
     }
 
+    #[test]
+    fn index_template_renders_in_the_site_locale() {
+        let mut list_of_lists: ListOfLists =
+            serde_json::from_str(EXAMPLE_LIST).expect("example list must deserialize");
+
+        let rendered = render_index_template(&list_of_lists);
+        assert!(
+            rendered.contains(r#"<html lang="en" dir="ltr">"#),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains(r#"aria-label="Main navigation""#),
+            "{rendered}"
+        );
+        assert!(!rendered.contains("bootstrap.rtl.min.css"), "{rendered}");
+
+        list_of_lists.locale = Some("ar".to_string());
+        let rendered = render_index_template(&list_of_lists);
+        assert!(
+            rendered.contains(r#"<html lang="ar" dir="rtl">"#),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains(r#"aria-label="التنقل الرئيسي""#),
+            "{rendered}"
+        );
+        assert!(!rendered.contains("Main navigation"), "{rendered}");
+        assert!(rendered.contains("bootstrap.rtl.min.css"), "{rendered}");
+        assert!(!rendered.contains("bootstrap.min.css"), "{rendered}");
+    }
+
+    #[test]
+    fn index_template_renders_new_badges() {
+        let list_of_lists: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "list": ["Soup", "Steak"]}]}"#,
+        )
+        .expect("list must deserialize");
+
+        let template = include_str!("../index.template");
+        let env = build_environment(template).expect("index.template must compile");
+        let rendered = env
+            .get_template(SITE_INDEX)
+            .expect("compiled template must be registered")
+            .render(context! {
+                site_url => "example.com",
+                first_seen => serde_json::json!({"Dinner": {"Soup": null, "Steak": "2026-01-02T03:04:05Z"}}),
+                new_items => serde_json::json!({"Dinner": ["Steak"]}),
+                ui => Value::from_serialize(&locale::for_site(&list_of_lists).ui),
+                theme => Value::from_serialize(theme::context(None, None)),
+                ..Value::from_serialize(&list_of_lists)
+            })
+            .expect("index.template must render");
+
+        assert!(
+            rendered.contains(
+                r#"Steak <span class="badge rounded-pill text-bg-success" title="Added 2026-01-02">New</span>"#
+            ),
+            "{rendered}"
+        );
+        assert_eq!(1, rendered.matches(">New</span>").count(), "{rendered}");
+    }
+
+    #[test]
+    fn index_template_renders_tag_filters() {
+        let list_of_lists: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "list": [
+                {"item": "Soup", "tags": ["veg", "cheap"]},
+                {"item": "Steak", "tooltip": "Medium rare", "tags": ["cheap"]},
+                "Bread"
+            ]}]}"#,
+        )
+        .expect("tagged list must deserialize");
+
+        let rendered = render_index_template(&list_of_lists);
+
+        assert!(rendered.contains(r#"data-tags="veg cheap""#), "{rendered}");
+        assert!(rendered.contains(r#"data-tag-filter="veg""#), "{rendered}");
+        assert!(
+            rendered.contains(r#"data-tag-filter="cheap""#),
+            "{rendered}"
+        );
+        assert!(rendered.contains("Medium rare"), "{rendered}");
+    }
+
+    #[test]
+    fn index_template_renders_list_styles() {
+        let list_of_lists: ListOfLists = serde_json::from_str(
+            r#"{"title": "Styles", "lists": [
+                {"title": "Ranked", "start": 0, "list": ["a"]},
+                {"title": "Bullets", "style": "unordered", "list": ["b"]},
+                {"title": "Todo", "style": "checklist", "list": [{"item": "c", "tooltip": "see"}]},
+                {"title": "Row", "style": "inline", "list": ["d"]}
+            ]}"#,
+        )
+        .expect("styled lists must deserialize");
+
+        let rendered = render_index_template(&list_of_lists);
+
+        assert!(rendered.contains(r#"<ol start="0">"#), "{rendered}");
+        assert!(rendered.contains("<ul>"), "{rendered}");
+        assert!(
+            rendered
+                .contains(r#"<input class="form-check-input" type="checkbox" id="check_Todo_1">"#),
+            "{rendered}"
+        );
+        assert!(rendered.contains(r#"for="check_Todo_1""#), "{rendered}");
+        assert!(
+            rendered.contains(r#"<li class="list-inline-item">d</li>"#),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn index_template_renders_list_descriptions_and_icons() {
+        let list_of_lists: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [
+                {"title": "Dinner", "description": "Evening meals", "icon": "bi bi-moon", "list": ["Soup"]},
+                {"title": "Lunch", "description": "Midday meals", "list": ["Salad"]},
+                {"title": "Secret", "description": "Not shared", "hidden": true, "list": ["Cake"]}
+            ]}"#,
+        )
+        .expect("described lists must deserialize");
+
+        let rendered = render_index_template(&list_of_lists);
+
+        assert!(
+            rendered.contains(r#"<i class="bi bi-moon me-1" aria-hidden="true"></i>Dinner</a>"#),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains(r#"<p class="lead">Evening meals</p>"#),
+            "{rendered}"
+        );
+        assert!(
+            rendered
+                .contains(r#"property="og:description" content="Evening meals · Midday meals""#),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains(r#""description": "Evening meals","#),
+            "{rendered}"
+        );
+        assert!(!rendered.contains("Not shared"), "{rendered}");
+
+        let ld_json = rendered
+            .split(r#"<script type="application/ld+json">"#)
+            .nth(1)
+            .and_then(|s| s.split("</script>").next())
+            .expect("JSON-LD block must render");
+        let ld: serde_json::Value =
+            serde_json::from_str(ld_json).expect("JSON-LD must be valid JSON");
+        assert_eq!(
+            2,
+            ld["mainEntity"]["itemListElement"]
+                .as_array()
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn index_template_renders_the_theme() {
+        let mut list_of_lists: ListOfLists = serde_json::from_str(
+            r##"{"title": "Food", "lists": [{"title": "Dinner", "list": ["Soup"]}],
+                "theme": {"primary_color": "#06f", "color_scheme": "dark",
+                          "font_family": "\"Inter Variable\", sans-serif"}}"##,
+        )
+        .expect("themed list must deserialize");
+
+        let rendered = render_index_template(&list_of_lists);
+        for expected in [
+            r#"<html lang="en" dir="ltr" data-bs-theme="dark">"#,
+            r##"<meta name="theme-color" content="#06f">"##,
+            "--bs-primary-rgb: 0, 102, 255;",
+            r#"--bs-body-font-family: "Inter Variable", sans-serif;"#,
+            r#"<nav class="navbar navbar-expand-lg bg-primary navbar-dark sticky-top""#,
+        ] {
+            assert!(rendered.contains(expected), "{expected}: {rendered}");
+        }
+        assert!(!rendered.contains("prefers-color-scheme"), "{rendered}");
+
+        list_of_lists.theme.as_mut().unwrap().color_scheme = crate::ColorScheme::Auto;
+        let rendered = render_index_template(&list_of_lists);
+        assert!(
+            rendered.contains(r#"<html lang="en" dir="ltr">"#),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains("prefers-color-scheme: dark"),
+            "{rendered}"
+        );
+
+        let rendered = render_index_template(&serde_json::from_str(EXAMPLE_LIST).unwrap());
+        assert!(
+            rendered.contains(r##"<meta name="theme-color" content="#212529">"##),
+            "{rendered}"
+        );
+        assert!(!rendered.contains("--bs-primary:"), "{rendered}");
+    }
+
+    #[tokio::test]
+    async fn render_site_inlines_custom_css() -> Result<()> {
+        let dir = TempDir::new("custom-css");
+        let io = dir.io();
+        fs::create_dir_all(dir.join("generator/styles")).await?;
+        fs::write(
+            dir.join("generator/example.com.json"),
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "list": ["Soup"]}],
+                "theme": {"custom_css": "styles/site.css"}}"#,
+        )
+        .await?;
+        fs::write(
+            dir.join("generator/styles/site.css"),
+            ".navbar { letter-spacing: 1px; }",
+        )
+        .await?;
+        let env = build_environment(include_str!("../index.template"))?;
+        let import_policy = ImportPolicy::default();
+        let render = || {
+            render_site(
+                &io,
+                &env,
+                "example.com",
+                Utc::now(),
+                &import_policy,
+                RenderOptions::default(),
+            )
+        };
+
+        render().await?;
+        let page = fs::read_to_string(dir.join("site/index.html")).await?;
+        assert!(page.contains(".navbar { letter-spacing: 1px; }"), "{page}");
+        assert_eq!(
+            BTreeSet::from(["styles/site.css".to_string()]),
+            site_includes(&io, "example.com").await?
+        );
+
+        fs::write(dir.join("generator/styles/site.css"), "</style><script>").await?;
+        assert!(
+            format!("{:#}", render().await.unwrap_err()).contains("must not contain \"</style\""),
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strict_render_fails_on_warnings_before_publishing() -> Result<()> {
+        let dir = TempDir::new("strict");
+        let io = dir.io();
+        fs::create_dir_all(dir.join("generator")).await?;
+        fs::write(
+            dir.join("generator/example.com.json"),
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "hidden": true, "list": ["Soup"]}]}"#,
+        )
+        .await?;
+        let env = build_environment(include_str!("../index.template"))?;
+        let strict = RenderOptions {
+            strict: true,
+            ..RenderOptions::default()
+        };
+
+        let err = render_site(
+            &io,
+            &env,
+            "example.com",
+            Utc::now(),
+            &ImportPolicy::default(),
+            strict,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Every list is hidden"), "{err:#}");
+        assert!(!dir.join("site").join(SITE_INDEX).exists());
+
+        render_site(
+            &io,
+            &env,
+            "example.com",
+            Utc::now(),
+            &ImportPolicy::default(),
+            RenderOptions::default(),
+        )
+        .await?;
+        assert!(dir.join("site").join(SITE_INDEX).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn visibility_changed_since_last_render() -> Result<()> {
+        let dir = TempDir::new("visibility");
+        let io = dir.io();
+        fs::create_dir_all(dir.join("generator")).await?;
+        fs::write(
+            dir.join("generator/example.com.json"),
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "list": ["Soup"]},
+                {"title": "Lunch", "list": ["Salad"], "publish_at": "2026-01-01T12:00:00Z"}]}"#,
+        )
+        .await?;
+        let time = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        let env = build_environment(include_str!("../index.template"))?;
+
+        assert!(visibility_changed(&io, "example.com", time("2026-01-01T00:00:00Z")).await?);
+        render_site(
+            &io,
+            &env,
+            "example.com",
+            time("2026-01-01T00:00:00Z"),
+            &ImportPolicy::default(),
+            RenderOptions::default(),
+        )
+        .await?;
+        assert!(!visibility_changed(&io, "example.com", time("2026-01-01T11:59:59Z")).await?);
+        // However many runs were missed in between.
+        assert!(visibility_changed(&io, "example.com", time("2026-01-02T00:00:00Z")).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_csp_meta() {
+        let site = b"<!doctype html><html><HEAD lang=en><title>x</title></head></html>".to_vec();
+        let csp = ContentSecurityPolicy::from_html("", &Default::default());
+
+        let site = String::from_utf8(insert_csp_meta(site, &csp).unwrap()).unwrap();
+
+        assert!(
+            site.starts_with(
+                r#"<!doctype html><html><HEAD lang=en><meta http-equiv="Content-Security-Policy" content="base-uri 'self';"#
+            ),
+            "{site}"
+        );
+        assert!(insert_csp_meta(b"<p>no head</p>".to_vec(), &csp).is_err());
+
+        // The charset declaration stays first.
+        let site = b"<html><head>\n<meta charset=utf-8><title>x</title></head></html>".to_vec();
+        let site = String::from_utf8(insert_csp_meta(site, &csp).unwrap()).unwrap();
+        assert!(
+            site.starts_with(
+                r#"<html><head>
+<meta charset=utf-8><meta http-equiv="Content-Security-Policy" content="base-uri 'self';"#
+            ),
+            "{site}"
+        );
+    }
+
     #[test]
     fn index_template_renders_a_minimal_site() {
         // No description, no footer, no footer links — every optional branch skipped.
@@ -378,5 +1359,75 @@ mod test {
             rendered.contains(r#"name="description" content="Bare""#),
             "{rendered}"
         );
+        assert!(!rendered.contains(r#"data-tag-filter=""#), "{rendered}");
+        assert!(rendered.contains("<ol>"), "{rendered}");
+    }
+
+    #[tokio::test]
+    async fn render_site_publishes_each_language() -> Result<()> {
+        let dir = TempDir::new("languages");
+        let io = dir.io();
+        fs::create_dir_all(dir.join("generator")).await?;
+        fs::write(
+            dir.join("generator").join("example.com.json"),
+            r#"{"title": "Food", "translations": {"es": {"title": "Comida"}},
+                "lists": [{"title": "Dinner", "list": ["Soup"],
+                           "translations": {"es": {"title": "Cena", "list": ["Sopa"]}}}]}"#,
+        )
+        .await?;
+        let env = build_environment(include_str!("../index.template"))?;
+        let import_policy = ImportPolicy::default();
+        let render = || {
+            render_site(
+                &io,
+                &env,
+                "example.com",
+                Utc::now(),
+                &import_policy,
+                RenderOptions::default(),
+            )
+        };
+
+        assert_eq!(vec!["index.html", "es/index.html"], render().await?);
+
+        // Auto-escaping writes '/' in attribute values as an entity.
+        let read = |page: &str| {
+            let path = dir.join("site").join(page);
+            async move { Ok::<_, anyhow::Error>(fs::read_to_string(path).await?.replace("&#x2f;", "/")) }
+        };
+        let es = read("es/index.html").await?;
+        assert!(es.contains(r#"<html lang="es" dir="ltr">"#), "{es}");
+        assert!(es.contains("Sopa"), "{es}");
+        assert!(es.contains(r#"href="../images/favicon.ico""#), "{es}");
+        assert!(
+            es.contains(r#"<link rel="canonical" href="https://example.com/es/">"#),
+            "{es}"
+        );
+        for page in [es, read("index.html").await?] {
+            assert!(
+                page.contains(
+                    r#"<link rel="alternate" hreflang="en" href="https://example.com/">"#
+                ),
+                "{page}"
+            );
+            assert!(
+                page.contains(
+                    r#"<link rel="alternate" hreflang="es" href="https://example.com/es/">"#
+                ),
+                "{page}"
+            );
+        }
+
+        // Dropping the language removes its page, which is returned for invalidation.
+        fs::write(
+            dir.join("generator").join("example.com.json"),
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "list": ["Soup"]}]}"#,
+        )
+        .await?;
+        assert_eq!(vec!["index.html", "es/index.html"], render().await?);
+        assert!(!dir.join("site/es/index.html").exists());
+        assert!(!dir.join("site/es/index.html.br").exists());
+        assert_eq!(vec!["index.html"], render().await?);
+        Ok(())
     }
 }
@@ -0,0 +1,114 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Unquoted values run to whitespace or '>', as browsers parse them; minify-html
+// emits values like `onload=this.onload=null` that stricter grammars would cut short.
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(?:^|\s)([a-z_][a-z0-9_:-]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+)))?"#)
+        .unwrap()
+});
+
+// Name/value pairs from the attribute section of a start tag. Names are
+// lowercased; valueless attributes like `defer` get an empty value.
+pub(crate) fn attributes(attrs: &str) -> Vec<(String, String)> {
+    ATTRIBUTE
+        .captures_iter(attrs)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or(caps.get(3))
+                .or(caps.get(4))
+                .map_or("", |m| m.as_str());
+            (caps[1].to_ascii_lowercase(), decode_entities(value))
+        })
+        .collect()
+}
+
+pub(crate) fn attr(attrs: &str, name: &str) -> Option<String> {
+    attributes(attrs)
+        .into_iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+// Attribute values are compared and hashed as the browser sees them, after entity decoding.
+pub(crate) fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+// Escapes text for HTML/XML content and double- or single-quoted attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Splits an absolute or protocol-relative http(s) URL into its lowercased scheme
+// (None when protocol-relative) and host[:port].
+pub(crate) fn url_origin(url: &str) -> Option<(Option<String>, String)> {
+    static ORIGIN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?i)^(?:(https?):)?//([a-z0-9.-]+(?::[0-9]+)?)(?:[/?#]|$)").unwrap()
+    });
+
+    let caps = ORIGIN.captures(url.trim())?;
+    Some((
+        caps.get(1).map(|s| s.as_str().to_ascii_lowercase()),
+        caps[2].to_ascii_lowercase(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_attributes() {
+        assert_eq!(
+            vec![
+                ("src".to_string(), "a.js?x=1&y=2".to_string()),
+                ("defer".to_string(), String::new()),
+                ("data-x".to_string(), "it's".to_string()),
+                ("crossorigin".to_string(), "anonymous".to_string()),
+                (
+                    "onload".to_string(),
+                    "this.onload=null;this.rel='stylesheet'".to_string()
+                ),
+            ],
+            attributes(
+                r#" SRC="a.js?x=1&amp;y=2" defer data-x='it&#39;s' crossorigin=anonymous onload=this.onload=null;this.rel='stylesheet'"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_escape_round_trips_through_decode() {
+        let text = r#"<a href="x">Tom & Jerry's</a>"#;
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;",
+            escape(text)
+        );
+        assert_eq!(text, decode_entities(&escape(text)));
+    }
+
+    #[test]
+    fn test_url_origin() {
+        assert_eq!(
+            Some((Some("https".to_string()), "cdn.example.com".to_string())),
+            url_origin("HTTPS://CDN.example.com/a.js")
+        );
+        assert_eq!(
+            Some((None, "cdn.example.com:8443".to_string())),
+            url_origin("//cdn.example.com:8443")
+        );
+        assert_eq!(None, url_origin("images/favicon.ico"));
+        assert_eq!(None, url_origin("javascript:alert(1)"));
+    }
+}
@@ -0,0 +1,358 @@
+use crate::html::{attr, attributes, escape, url_origin};
+use crate::{IMPORT_ORIGINS_VAR, REQUIRE_INTEGRITY_VAR};
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::env;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+// Used when a deployment doesn't configure its own allowlist.
+pub const DEFAULT_IMPORT_ORIGINS: &[&str] = &[
+    "https://cdn.jsdelivr.net",
+    "https://kit.fontawesome.com",
+    "https://use.fontawesome.com",
+];
+
+const LINK_RELS: &[&str] = &["stylesheet", "preload", "icon"];
+
+// Deployment-wide rules for footer.imports, on top of the structural checks in
+// ListOfLists::validate.
+#[derive(Debug, Clone)]
+pub struct ImportPolicy {
+    allowed_origins: Vec<String>,
+    require_integrity: bool,
+}
+
+impl Default for ImportPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_IMPORT_ORIGINS.iter().map(|o| o.to_string()))
+    }
+}
+
+impl ImportPolicy {
+    pub fn new<I, S>(allowed_origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            allowed_origins: allowed_origins
+                .into_iter()
+                .map(|o| o.into().trim().trim_end_matches('/').to_ascii_lowercase())
+                .filter(|o| !o.is_empty())
+                .collect(),
+            require_integrity: false,
+        }
+    }
+
+    // Every import is cross-origin, so requiring integrity means every tag needs one.
+    pub fn require_integrity(mut self, require_integrity: bool) -> Self {
+        self.require_integrity = require_integrity;
+        self
+    }
+
+    // Comma-separated origins from LOL_IMPORT_ORIGINS, falling back to the defaults,
+    // and LOL_REQUIRE_INTEGRITY=true to enforce integrity.
+    pub fn from_env() -> Self {
+        let policy = match env::var(IMPORT_ORIGINS_VAR) {
+            Ok(origins) => Self::new(origins.split(',')),
+            Err(_) => Self::default(),
+        };
+        policy.require_integrity(env::var(REQUIRE_INTEGRITY_VAR).is_ok_and(|v| v == "true"))
+    }
+
+    pub(crate) fn check(&self, imports: &[String]) -> Result<()> {
+        for (i, import) in imports.iter().enumerate() {
+            for tag in parse_import(import).map_err(|e| import_error(i, import, e))? {
+                let origin = tag.origin();
+                if !self.allowed_origins.contains(&origin) {
+                    return Err(import_error(
+                        i,
+                        import,
+                        format!(
+                            "origin {origin:?} is not in the allowed import origins {:?}",
+                            self.allowed_origins
+                        ),
+                    ));
+                }
+                if self.require_integrity && attr(&tag.attrs, "integrity").is_none() {
+                    return Err(import_error(
+                        i,
+                        import,
+                        format!("<{}> {:?} has no integrity attribute", tag.name, tag.url),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn validate_imports(imports: &[String]) -> Result<()> {
+    for (i, import) in imports.iter().enumerate() {
+        parse_import(import).map_err(|e| import_error(i, import, e))?;
+    }
+    Ok(())
+}
+
+fn import_error(index: usize, import: &str, reason: String) -> anyhow::Error {
+    anyhow!("footer.imports[{index}] {import:?}: {reason}")
+}
+
+// Rewrites each tag in an import with the integrity `integrity_for` returns for its
+// URL, adding crossorigin="anonymous" unless the tag already sets crossorigin.
+// Whitespace between tags is kept; attributes are re-serialized with double quotes.
+pub(crate) fn with_integrity<F>(import: &str, mut integrity_for: F) -> Result<String>
+where
+    F: FnMut(&str) -> Result<String>,
+{
+    let tags = parse_import(import).map_err(|e| anyhow!("{import:?}: {e}"))?;
+
+    let mut rewritten = import.to_string();
+    for tag in tags.iter().rev() {
+        let integrity = integrity_for(&tag.url)?;
+        let mut attrs = attributes(&tag.attrs);
+        match attrs.iter_mut().find(|(n, _)| n == "integrity") {
+            Some((_, value)) => *value = integrity,
+            None => attrs.push(("integrity".to_string(), integrity)),
+        }
+        if !attrs.iter().any(|(n, _)| n == "crossorigin") {
+            attrs.push(("crossorigin".to_string(), "anonymous".to_string()));
+        }
+
+        let attrs: String = attrs
+            .iter()
+            .map(|(n, v)| match v.is_empty() {
+                true => format!(" {n}"),
+                false => format!(r#" {n}="{}""#, escape(v)),
+            })
+            .collect();
+        let tag_html = match tag.name {
+            "script" => format!("<script{attrs}></script>"),
+            name => format!("<{name}{attrs}>"),
+        };
+        rewritten.replace_range(tag.span.clone(), &tag_html);
+    }
+    Ok(rewritten)
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ImportTag {
+    pub name: &'static str,
+    pub attrs: String,
+    pub url: String,
+    // Byte range of the whole tag within the import.
+    pub span: Range<usize>,
+}
+
+impl ImportTag {
+    fn origin(&self) -> String {
+        // parse_import only accepts https URLs, so the origin is always present.
+        let (_, host) = url_origin(&self.url).unwrap_or_default();
+        format!("https://{host}")
+    }
+}
+
+// Splits an import into its tags, accepting only `<script src></script>` and
+// `<link rel=stylesheet|preload|icon href>` with absolute https URLs and no
+// inline event handlers. Anything else, including inline script bodies, text
+// and comments, is rejected, as is a malformed integrity or one without the
+// crossorigin attribute browsers need to check it.
+pub(crate) fn parse_import(import: &str) -> Result<Vec<ImportTag>, String> {
+    static SCRIPT: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)\A<script\b([^>]*)>(.*?)</script\s*>").unwrap());
+    static LINK: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)\A<link\b([^>]*?)/?>").unwrap());
+    static INTEGRITY: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^sha(?:256|384|512)-[A-Za-z0-9+/]+={0,2}$").unwrap());
+
+    let mut tags = Vec::new();
+    let mut rest = import.trim_start();
+    while !rest.is_empty() {
+        let start = import.len() - rest.len();
+        let (name, attrs, url, len) = if let Some(caps) = SCRIPT.captures(rest) {
+            if !caps[2].trim().is_empty() {
+                return Err("inline scripts are not allowed".to_string());
+            }
+            let url = attr(&caps[1], "src").ok_or("<script> must have a src")?;
+            ("script", caps[1].to_string(), url, caps[0].len())
+        } else if let Some(caps) = LINK.captures(rest) {
+            let rel = attr(&caps[1], "rel")
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            if rel.trim().is_empty() || !rel.split_whitespace().all(|r| LINK_RELS.contains(&r)) {
+                return Err(format!("<link> rel {rel:?} must be one of {LINK_RELS:?}"));
+            }
+            let url = attr(&caps[1], "href").ok_or("<link> must have an href")?;
+            ("link", caps[1].to_string(), url, caps[0].len())
+        } else {
+            let snippet: String = rest.chars().take(40).collect();
+            return Err(format!(
+                "only <script src> and <link> tags are allowed, found {snippet:?}"
+            ));
+        };
+
+        if let Some((handler, _)) = attributes(&attrs)
+            .into_iter()
+            .find(|(n, _)| n.starts_with("on"))
+        {
+            return Err(format!(
+                "inline event handler {handler:?} on <{name}> is not allowed"
+            ));
+        }
+        if !matches!(url_origin(&url), Some((Some(scheme), _)) if scheme == "https") {
+            return Err(format!(
+                "<{name}> URL {url:?} must be an absolute https URL"
+            ));
+        }
+        if let Some(integrity) = attr(&attrs, "integrity") {
+            if integrity.split_whitespace().next().is_none()
+                || !integrity.split_whitespace().all(|i| INTEGRITY.is_match(i))
+            {
+                return Err(format!(
+                    "integrity {integrity:?} must be sha256-, sha384- or sha512- base64 hashes"
+                ));
+            }
+            if attr(&attrs, "crossorigin").is_none() {
+                return Err(format!(
+                    "<{name}> with integrity must set crossorigin, or browsers block it"
+                ));
+            }
+        }
+        tags.push(ImportTag {
+            name,
+            attrs,
+            url,
+            span: start..start + len,
+        });
+        rest = rest[len..].trim_start();
+    }
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn err(import: &str) -> String {
+        parse_import(import).expect_err(import)
+    }
+
+    #[test]
+    fn test_parse_import_accepts_script_and_links() {
+        let tags = parse_import(concat!(
+            r#"<script src="https://kit.fontawesome.com/deadbeef.js" crossorigin="anonymous"></script>"#,
+            "\n",
+            r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/a.css" />"#,
+            r#"<link rel=icon href=https://cdn.jsdelivr.net/favicon.ico>"#,
+        ))
+        .unwrap();
+
+        let names: Vec<_> = tags.iter().map(|t| t.name).collect();
+        assert_eq!(vec!["script", "link", "link"], names);
+        assert_eq!("https://kit.fontawesome.com/deadbeef.js", tags[0].url);
+        assert_eq!("https://cdn.jsdelivr.net", tags[2].origin());
+    }
+
+    #[test]
+    fn test_parse_import_rejects_unsafe_content() {
+        assert!(err("<script>alert(1)</script>").contains("inline scripts"));
+        assert!(
+            err(r#"<script src="https://a.com/a.js">alert(1)</script>"#).contains("inline scripts")
+        );
+        assert!(err(r#"<script async></script>"#).contains("must have a src"));
+        assert!(
+            err(r#"<link rel="stylesheet" href="https://a.com/a.css" onload="alert(1)">"#)
+                .contains(r#""onload""#)
+        );
+        assert!(err(r#"<link rel="manifest" href="https://a.com/m.json">"#).contains("rel"));
+        assert!(err(r#"<link href="https://a.com/a.css">"#).contains("rel"));
+        assert!(err(r#"<img src="https://a.com/x.png">"#).contains("only <script src>"));
+        assert!(err("hello").contains("only <script src>"));
+        assert!(err(r#"<script src="http://a.com/a.js"></script>"#).contains("https"));
+        assert!(err(r#"<script src="//a.com/a.js"></script>"#).contains("https"));
+        assert!(err(r#"<link rel="icon" href="javascript:alert(1)">"#).contains("https"));
+        assert!(
+            err(r#"<script src="https://a.com/a.js" integrity="md5-abc" crossorigin></script>"#)
+                .contains("sha384-")
+        );
+        assert!(
+            err(r#"<script src="https://a.com/a.js" integrity="sha384-abc"></script>"#)
+                .contains("crossorigin")
+        );
+    }
+
+    #[test]
+    fn test_policy_requires_integrity() {
+        let policy = ImportPolicy::new(["https://a.com"]).require_integrity(true);
+        let imports = |import: &str| vec![import.to_string()];
+
+        assert!(
+            policy
+                .check(&imports(
+                    r#"<script src="https://a.com/a.js" integrity="sha384-abc=" crossorigin="anonymous"></script>"#
+                ))
+                .is_ok()
+        );
+        let e = policy
+            .check(&imports(
+                r#"<link rel="stylesheet" href="https://a.com/a.css"><script src="https://a.com/a.js" integrity="sha384-abc=" crossorigin></script>"#,
+            ))
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("a.css"), "{e}");
+        assert!(e.contains("no integrity"), "{e}");
+    }
+
+    #[test]
+    fn test_with_integrity() {
+        let import = concat!(
+            r#"<script src="https://a.com/a.js?v=1&amp;x=2" defer></script>"#,
+            "\n",
+            r#"<link rel=stylesheet href=https://a.com/a.css integrity="sha256-old=" crossorigin="use-credentials" />"#,
+        );
+        let rewritten = with_integrity(import, |url| match url {
+            "https://a.com/a.js?v=1&x=2" => Ok("sha384-js".to_string()),
+            "https://a.com/a.css" => Ok("sha384-css".to_string()),
+            _ => Err(anyhow!("unexpected {url}")),
+        })
+        .unwrap();
+
+        assert_eq!(
+            concat!(
+                r#"<script src="https://a.com/a.js?v=1&amp;x=2" defer integrity="sha384-js" crossorigin="anonymous"></script>"#,
+                "\n",
+                r#"<link rel="stylesheet" href="https://a.com/a.css" integrity="sha384-css" crossorigin="use-credentials">"#,
+            ),
+            rewritten
+        );
+        assert!(parse_import(&rewritten).is_ok());
+        assert_eq!(
+            rewritten,
+            with_integrity(&rewritten, |url| Ok(if url.ends_with(".css") {
+                "sha384-css".to_string()
+            } else {
+                "sha384-js".to_string()
+            }))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_policy_checks_origins() {
+        let policy = ImportPolicy::new(["https://cdn.jsdelivr.net/", " https://A.com"]);
+        let imports = |import: &str| vec![import.to_string()];
+
+        assert!(
+            policy
+                .check(&imports(r#"<script src="https://a.com/a.js"></script>"#))
+                .is_ok()
+        );
+        let e = policy
+            .check(&imports(r#"<script src="https://evil.com/a.js"></script>"#))
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("footer.imports[0]"), "{e}");
+        assert!(e.contains("https://evil.com"), "{e}");
+    }
+}
@@ -0,0 +1,352 @@
+use crate::generator::Io;
+use crate::{List, ListOfLists};
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+
+// {"include": key} in a site's lists splices in the list(s) stored at key in the
+// generator bucket: a single list, or an array of lists and further includes.
+const INCLUDE: &str = "include";
+
+pub(crate) const LISTS: &str = "lists";
+pub(crate) const FOOTER: &str = "footer";
+pub(crate) const CSP_SOURCES: &str = "csp_sources";
+pub(crate) const THEME: &str = "theme";
+
+// Site fields that can be {"include": key} as a whole, so sites can share them. The
+// CSP sources a shared footer's imports need are usually shared along with it.
+const FRAGMENT_FIELDS: [&str; 3] = [FOOTER, CSP_SOURCES, THEME];
+
+// The key an entry includes, if it's an include rather than a value.
+pub(crate) fn include_key(entry: &Value) -> Option<&str> {
+    entry
+        .as_object()
+        .filter(|o| o.len() == 1)
+        .and_then(|o| o.get(INCLUDE))
+        .and_then(Value::as_str)
+}
+
+// The entry that includes `key`, as written in site JSON.
+pub(crate) fn include_value(key: &str) -> Value {
+    json!({INCLUDE: key})
+}
+
+// Keys are relative to the generator bucket. Its top-level JSON files are sites, so
+// included files live in a directory; ".." and absolute paths would escape it locally.
+// `extension` is the file type the key must name, e.g. ".json".
+pub(crate) fn validate_key(key: &str, extension: &str) -> Result<()> {
+    if !key.ends_with(extension)
+        || !key.contains('/')
+        || key.starts_with('/')
+        || key
+            .split('/')
+            .any(|segment| matches!(segment, "" | "." | ".."))
+    {
+        bail!(
+            "{key:?} must be a relative path to a {extension} file, like \"dir/name{extension}\""
+        );
+    }
+    Ok(())
+}
+
+// Splices every include in `site`'s lists and fragment fields, reading them through
+// `io`. Returns each key read, whether included by the site or by another included file.
+pub(crate) async fn expand(io: &Io, site_key: &str, site: &mut Value) -> Result<BTreeSet<String>> {
+    let mut included = BTreeSet::new();
+    for field in FRAGMENT_FIELDS {
+        let Some(key) = site.get(field).and_then(include_key).map(String::from) else {
+            continue;
+        };
+        validate_key(&key, ".json").with_context(|| format!("{field} in {site_key}"))?;
+        let fragment = read_included(io, &key, site_key).await?;
+        // Parse the fragment as its field on its own, so errors name its file.
+        serde_json::from_value::<ListOfLists>(json!({"title": "", LISTS: [], field: fragment}))
+            .with_context(|| format!("parse {key} as {field}"))?;
+        site[field] = fragment;
+        included.insert(key);
+    }
+
+    // A missing or malformed lists is left for ListOfLists parsing to report. Lists
+    // included as a whole are spliced like a single entry including them.
+    if let Some(lists) = site.get_mut(LISTS) {
+        let entries = match &mut *lists {
+            Value::Array(entries) => std::mem::take(entries),
+            include if include_key(include).is_some() => vec![include.take()],
+            _ => return Ok(included),
+        };
+        *lists = Value::Array(
+            expand_entries(io, entries, &mut vec![site_key.to_string()], &mut included).await?,
+        );
+    }
+    Ok(included)
+}
+
+async fn read_included(io: &Io, key: &str, from: &str) -> Result<Value> {
+    let content = io
+        .read(key)
+        .await
+        .with_context(|| format!("read {key} (included from {from})"))?;
+    serde_json::from_str(&content).with_context(|| format!("parse {key} (included from {from})"))
+}
+
+// `chain` is the path of includes to the file `entries` came from, for cycle detection.
+async fn expand_entries(
+    io: &Io,
+    entries: Vec<Value>,
+    chain: &mut Vec<String>,
+    included: &mut BTreeSet<String>,
+) -> Result<Vec<Value>> {
+    let from = chain.last().cloned().unwrap_or_default();
+    let mut expanded = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let Some(key) = include_key(&entry).map(String::from) else {
+            // Parse lists from included files here, so errors name the file.
+            if chain.len() > 1 {
+                serde_json::from_value::<List>(entry.clone())
+                    .with_context(|| format!("parse list in {from}"))?;
+            }
+            expanded.push(entry);
+            continue;
+        };
+        validate_key(&key, ".json").with_context(|| format!("include in {from}"))?;
+        if chain.contains(&key) {
+            bail!("include cycle: {} -> {key}", chain.join(" -> "));
+        }
+
+        let nested = match read_included(io, &key, &from).await? {
+            Value::Array(entries) => entries,
+            list @ Value::Object(_) => vec![list],
+            _ => bail!("{key} (included from {from}) must contain a list or an array of lists"),
+        };
+        included.insert(key.clone());
+
+        chain.push(key);
+        expanded.extend(Box::pin(expand_entries(io, nested, chain, included)).await?);
+        chain.pop();
+    }
+    Ok(expanded)
+}
+
+// Includes taken out of a site's own JSON, for rewriting the rest without reading
+// what it includes.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Includes {
+    // Fragment fields (and lists as a whole) by name.
+    pub(crate) fields: BTreeMap<String, String>,
+    // Included entries of lists, by position.
+    pub(crate) lists: Vec<(usize, String)>,
+}
+
+// Removes includes from a site, leaving JSON that parses as the site's own content.
+pub(crate) fn take_includes(site: &mut Value) -> Includes {
+    let mut includes = Includes::default();
+    let Some(fields) = site.as_object_mut() else {
+        return includes;
+    };
+    for field in FRAGMENT_FIELDS {
+        if let Some(key) = fields.get(field).and_then(include_key).map(String::from) {
+            fields.remove(field);
+            includes.fields.insert(field.to_string(), key);
+        }
+    }
+    match fields.get_mut(LISTS) {
+        Some(Value::Array(lists)) => {
+            includes.lists = lists
+                .iter()
+                .enumerate()
+                .filter_map(|(i, entry)| include_key(entry).map(|key| (i, key.to_string())))
+                .collect();
+            lists.retain(|entry| include_key(entry).is_none());
+        }
+        Some(lists) => {
+            if let Some(key) = include_key(lists).map(String::from) {
+                *lists = Value::Array(Vec::new());
+                includes.fields.insert(LISTS.to_string(), key);
+            }
+        }
+        None => {}
+    }
+    includes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+    use serde_json::json;
+    use tokio::fs;
+
+    async fn io_with(name: &str, files: &[(&str, Value)]) -> Result<(Io, TempDir)> {
+        let dir = TempDir::new(&format!("include-{name}"));
+        for (key, content) in files {
+            let path = dir.join("generator").join(key);
+            fs::create_dir_all(path.parent().unwrap()).await?;
+            fs::write(path, content.to_string()).await?;
+        }
+        Ok((dir.io(), dir))
+    }
+
+    #[tokio::test]
+    async fn test_expand_splices_nested_includes() -> Result<()> {
+        let (io, _dir) = io_with(
+            "nested",
+            &[
+                (
+                    "lists/books.json",
+                    json!({"title": "Books", "list": ["Dune"]}),
+                ),
+                (
+                    "lists/media.json",
+                    json!([{"include": "lists/books.json"}, {"title": "Films", "list": ["Heat"]}]),
+                ),
+            ],
+        )
+        .await?;
+
+        let mut site = json!({"title": "Site", "lists": [
+            {"title": "Food", "list": ["Soup"]},
+            {"include": "lists/media.json"}
+        ]});
+        let included = expand(&io, "site.json", &mut site).await?;
+        assert_eq!(
+            vec!["Food", "Books", "Films"],
+            site["lists"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|l| l["title"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            BTreeSet::from([
+                "lists/books.json".to_string(),
+                "lists/media.json".to_string()
+            ]),
+            included
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expand_errors_name_files() -> Result<()> {
+        let (io, _dir) = io_with(
+            "errors",
+            &[
+                ("lists/a.json", json!([{"include": "lists/b.json"}])),
+                ("lists/b.json", json!([{"include": "lists/a.json"}])),
+                ("lists/bad.json", json!({"list": ["No title"]})),
+            ],
+        )
+        .await?;
+
+        let mut site = json!({"lists": [{"include": "lists/a.json"}]});
+        assert_eq!(
+            "include cycle: site.json -> lists/a.json -> lists/b.json -> lists/a.json",
+            expand(&io, "site.json", &mut site)
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+
+        let mut site = json!({"lists": [{"include": "lists/bad.json"}]});
+        assert_eq!(
+            "parse list in lists/bad.json",
+            expand(&io, "site.json", &mut site)
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+
+        let mut site = json!({"lists": [{"include": "lists/missing.json"}]});
+        assert_eq!(
+            "read lists/missing.json (included from site.json)",
+            expand(&io, "site.json", &mut site)
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+
+        for key in [
+            "books.json",
+            "../books.json",
+            "/lists/books.json",
+            "lists/books.txt",
+        ] {
+            let mut site = json!({"lists": [{"include": key}]});
+            assert!(expand(&io, "site.json", &mut site).await.is_err(), "{key}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_includes() {
+        let mut site = json!({
+            "lists": [
+                {"include": "lists/a.json"},
+                {"title": "B", "list": []},
+                {"include": "lists/c.json"}
+            ],
+            "footer": {"include": "shared/footer.json"}
+        });
+        assert_eq!(
+            Includes {
+                fields: BTreeMap::from([("footer".to_string(), "shared/footer.json".to_string())]),
+                lists: vec![
+                    (0, "lists/a.json".to_string()),
+                    (2, "lists/c.json".to_string())
+                ],
+            },
+            take_includes(&mut site)
+        );
+        assert_eq!(json!({"lists": [{"title": "B", "list": []}]}), site);
+
+        let mut site = json!({"lists": {"include": "shared/lists.json"}});
+        assert_eq!(
+            BTreeMap::from([("lists".to_string(), "shared/lists.json".to_string())]),
+            take_includes(&mut site).fields
+        );
+        assert_eq!(json!({"lists": []}), site);
+    }
+
+    #[tokio::test]
+    async fn test_expand_fragments() -> Result<()> {
+        let (io, _dir) = io_with(
+            "fragments",
+            &[
+                (
+                    "shared/footer.json",
+                    json!({"links": [{"url": "https://a.com", "icon": "bi bi-house"}]}),
+                ),
+                ("shared/lists.json", json!([{"title": "A", "list": ["a"]}])),
+                ("shared/bad-footer.json", json!({"links": "none"})),
+            ],
+        )
+        .await?;
+
+        let mut site = json!({
+            "title": "Site",
+            "lists": {"include": "shared/lists.json"},
+            "footer": {"include": "shared/footer.json"}
+        });
+        let included = expand(&io, "site.json", &mut site).await?;
+        assert_eq!("https://a.com", site["footer"]["links"][0]["url"]);
+        assert_eq!("A", site["lists"][0]["title"]);
+        assert_eq!(
+            BTreeSet::from([
+                "shared/footer.json".to_string(),
+                "shared/lists.json".to_string()
+            ]),
+            included
+        );
+
+        let mut site = json!({"lists": [], "footer": {"include": "shared/bad-footer.json"}});
+        assert_eq!(
+            "parse shared/bad-footer.json as footer",
+            expand(&io, "site.json", &mut site)
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        Ok(())
+    }
+}
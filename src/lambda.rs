@@ -3,12 +3,15 @@ use aws_lambda_events::s3::S3Event;
 use aws_sdk_cloudfront::Client as CloudFrontClient;
 use aws_sdk_cloudfront::types::{InvalidationBatch, Paths};
 use aws_sdk_s3::Client as S3Client;
+use chrono::{DateTime, Utc};
 use jluszcz_rust_utils::{aws, lambda};
 use lambda_runtime::LambdaEvent;
+use list_of_lists::imports::ImportPolicy;
 use list_of_lists::{APP_NAME, generator, s3util};
 use log::{debug, info, warn};
+use serde::Deserialize;
 use serde_json::{Value, json};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 use std::sync::LazyLock;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -31,33 +34,20 @@ async fn function(event: LambdaEvent<Value>) -> Result<Value, lambda_runtime::Er
     let s3_client = S3Client::new(&aws_config);
     let cloudfront_client = CloudFrontClient::new(&aws_config);
 
-    let event: S3Event = serde_json::from_value(event.payload)?;
-
-    let mut site_urls: Vec<String> = Vec::new();
-    let mut regenerate_all = false;
-
-    for record in event.records {
-        let bucket = record.s3.bucket.name;
-        let key = record.s3.object.key;
-        if let (Some(bucket), Some(key)) = (bucket, key) {
-            if key == generator::SITE_INDEX_TEMPLATE {
-                info!("Regenerating all sites on update of {bucket}/{key}");
-                regenerate_all = true;
-                break;
-            } else if let Some(site_url) = key.strip_suffix(".json") {
-                info!("Will update {site_url} on update of {bucket}/{key}");
-                site_urls.push(site_url.to_string());
-            }
+    let now = Utc::now();
+    let mut site_urls = match serde_json::from_value::<ScheduledEvent>(event.payload.clone()) {
+        Ok(scheduled) if scheduled.detail_type == "Scheduled Event" => {
+            info!(
+                "Checking visibility boundaries for scheduled run at {}",
+                scheduled.time
+            );
+            sites_with_visibility_change(&s3_client, &generator_bucket, now).await?
         }
-    }
-
-    if regenerate_all {
-        site_urls = s3util::list_keys(&s3_client, &generator_bucket, ".json")
-            .await?
-            .into_iter()
-            .filter_map(|k| k.strip_suffix(".json").map(String::from))
-            .collect();
-    }
+        _ => {
+            let event: S3Event = serde_json::from_value(event.payload)?;
+            sites_for_s3_event(&s3_client, &generator_bucket, event).await?
+        }
+    };
 
     // Dedupe so duplicate S3 events don't trigger duplicate renders or invalidations.
     site_urls.sort();
@@ -78,25 +68,32 @@ async fn function(event: LambdaEvent<Value>) -> Result<Value, lambda_runtime::Er
         })
         .collect();
 
+    let import_policy = ImportPolicy::from_env();
+    let options = generator::RenderOptions {
+        minify: MINIFY,
+        vendor_assets: env::var(list_of_lists::VENDOR_ASSETS_VAR).is_ok_and(|v| v == "true"),
+        strict: false,
+    };
+
     // site_urls (and therefore ios) is non-empty here, and the template is
     // shared, so read it through the first site's Io.
     let template = ios[0].read_template().await?;
     let env = generator::build_environment(&template)?;
 
     let render_futures = site_urls.iter().zip(&ios).map(|(site_url, io)| {
-        let env = &env;
+        let (env, import_policy) = (&env, &import_policy);
         async move {
             info!("Updating {site_url}");
-            generator::render_site(io, env, site_url, MINIFY).await
+            generator::render_site(io, env, site_url, now, import_policy, options).await
         }
     });
     let render_results = futures::future::join_all(render_futures).await;
 
-    let mut rendered_sites: Vec<String> = Vec::new();
+    let mut rendered_sites: Vec<(String, Vec<String>)> = Vec::new();
     let mut render_failures = 0usize;
     for (site_url, result) in site_urls.iter().zip(render_results) {
         match result {
-            Ok(()) => rendered_sites.push(site_url.clone()),
+            Ok(pages) => rendered_sites.push((site_url.clone(), pages)),
             Err(err) => {
                 warn!("Failed to render {site_url}: {err:#}");
                 render_failures += 1;
@@ -110,21 +107,24 @@ async fn function(event: LambdaEvent<Value>) -> Result<Value, lambda_runtime::Er
     // Group rendered sites by distribution_id so we issue one invalidation per
     // distribution even if duplicate events or multiple aliases collapse onto
     // the same one. Lookups are serial because they share the cache mutex.
-    let mut by_distribution: HashMap<String, Vec<String>> = HashMap::new();
-    for site_url in &rendered_sites {
+    let mut by_distribution: HashMap<String, Invalidation> = HashMap::new();
+    for (site_url, pages) in &rendered_sites {
         match distribution_id_for_alias(&cloudfront_client, site_url).await {
-            Ok(Some(distribution_id)) => by_distribution
-                .entry(distribution_id)
-                .or_default()
-                .push(site_url.clone()),
+            Ok(Some(distribution_id)) => {
+                let invalidation = by_distribution.entry(distribution_id).or_default();
+                invalidation.sites.push(site_url.clone());
+                invalidation.pages.extend(pages.iter().cloned());
+            }
             Ok(None) => warn!("No CloudFront distribution found with alias {site_url}"),
             Err(err) => warn!("CloudFront lookup failed for {site_url}: {err:#}"),
         }
     }
 
-    let invalidation_futures = by_distribution.iter().map(|(distribution_id, sites)| {
-        invalidate_distribution(&cloudfront_client, distribution_id, sites)
-    });
+    let invalidation_futures = by_distribution
+        .iter()
+        .map(|(distribution_id, invalidation)| {
+            invalidate_distribution(&cloudfront_client, distribution_id, invalidation)
+        });
     let results = futures::future::join_all(invalidation_futures).await;
     let mut invalidation_failures = 0usize;
     for ((distribution_id, _), result) in by_distribution.iter().zip(results) {
@@ -145,10 +145,140 @@ async fn function(event: LambdaEvent<Value>) -> Result<Value, lambda_runtime::Er
     Ok(json!({}))
 }
 
+// The subset of an EventBridge scheduled event we need; S3 events fail to parse as this.
+#[derive(Deserialize)]
+struct ScheduledEvent {
+    #[serde(rename = "detail-type")]
+    detail_type: String,
+    time: DateTime<Utc>,
+}
+
+async fn sites_for_s3_event(
+    s3_client: &S3Client,
+    generator_bucket: &str,
+    event: S3Event,
+) -> Result<Vec<String>> {
+    let mut site_urls: Vec<String> = Vec::new();
+
+    for record in event.records {
+        let bucket = record.s3.bucket.name;
+        let key = record.s3.object.key;
+        if let (Some(bucket), Some(key)) = (bucket, key) {
+            if key == generator::SITE_INDEX_TEMPLATE {
+                info!("Regenerating all sites on update of {bucket}/{key}");
+                return all_site_urls(s3_client, generator_bucket).await;
+            } else if [generator::HISTORY_DIR, generator::VENDOR_DIR]
+                .iter()
+                .any(|dir| key.starts_with(&format!("{dir}/")))
+            {
+                // Written by renders themselves, state and vendored assets alike.
+                debug!("Ignoring update of {bucket}/{key}");
+            } else if let Some(site_url) = site_url_for_key(&key) {
+                info!("Will update {site_url} on update of {bucket}/{key}");
+                site_urls.push(site_url.to_string());
+            } else if key.ends_with(".json") || key.ends_with(".css") {
+                site_urls.extend(sites_including(s3_client, generator_bucket, &key).await?);
+            }
+        }
+    }
+
+    Ok(site_urls)
+}
+
+async fn all_site_urls(s3_client: &S3Client, generator_bucket: &str) -> Result<Vec<String>> {
+    Ok(s3util::list_keys(s3_client, generator_bucket, ".json")
+        .await?
+        .into_iter()
+        .filter_map(|k| site_url_for_key(&k).map(String::from))
+        .collect())
+}
+
+// Site JSON lives at the top of the generator bucket; nested keys such as
+// vendored or included files are never sites.
+fn site_url_for_key(key: &str) -> Option<&str> {
+    key.strip_suffix(".json")
+        .filter(|site_url| !site_url.contains('/'))
+}
+
+// Sites whose last render went stale by `now`. Comparing against each site's own
+// last render, rather than the schedule's interval, means a run that's late or
+// fails doesn't lose a boundary. A site that can't be read is skipped with a
+// warning rather than failing the run, matching how render failures are handled.
+async fn sites_with_visibility_change(
+    s3_client: &S3Client,
+    generator_bucket: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>> {
+    let candidates = all_site_urls(s3_client, generator_bucket).await?;
+
+    let check_futures = candidates.iter().map(|site_url| async move {
+        let io = generator::Io::new(
+            site_url.clone(),
+            generator_bucket.to_string(),
+            Some(s3_client.clone()),
+        );
+        generator::visibility_changed(&io, site_url, now).await
+    });
+    let results = futures::future::join_all(check_futures).await;
+
+    let mut site_urls = Vec::new();
+    for (site_url, result) in candidates.into_iter().zip(results) {
+        match result {
+            Ok(true) => {
+                info!("Will update {site_url}: visibility boundary since its last render");
+                site_urls.push(site_url);
+            }
+            Ok(false) => debug!("No visibility boundary passed for {site_url}"),
+            Err(err) => warn!("Failed to check visibility boundaries for {site_url}: {err:#}"),
+        }
+    }
+    Ok(site_urls)
+}
+
+// Sites that include `key`, directly or through another included file. Like
+// sites_with_visibility_change, a site that can't be read is skipped with a warning.
+async fn sites_including(
+    s3_client: &S3Client,
+    generator_bucket: &str,
+    key: &str,
+) -> Result<Vec<String>> {
+    let candidates = all_site_urls(s3_client, generator_bucket).await?;
+
+    let check_futures = candidates.iter().map(|site_url| async move {
+        let io = generator::Io::new(
+            site_url.clone(),
+            generator_bucket.to_string(),
+            Some(s3_client.clone()),
+        );
+        generator::site_includes(&io, site_url).await
+    });
+    let results = futures::future::join_all(check_futures).await;
+
+    let mut site_urls = Vec::new();
+    for (site_url, result) in candidates.into_iter().zip(results) {
+        match result {
+            Ok(includes) if includes.contains(key) => {
+                info!("Will update {site_url} on update of included {key}");
+                site_urls.push(site_url);
+            }
+            Ok(_) => debug!("{site_url} doesn't include {key}"),
+            Err(err) => warn!("Failed to read includes for {site_url}: {err:#}"),
+        }
+    }
+    Ok(site_urls)
+}
+
+// The sites rendered for one distribution and the pages they published.
+#[derive(Default)]
+struct Invalidation {
+    sites: Vec<String>,
+    pages: BTreeSet<String>,
+}
+
 async fn invalidate_distribution(
     client: &CloudFrontClient,
     distribution_id: &str,
-    sites: &[String],
+    invalidation: &Invalidation,
 ) -> Result<()> {
     let nanos = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -157,9 +287,15 @@ async fn invalidate_distribution(
     let counter = INVALIDATION_COUNTER.fetch_add(1, Ordering::Relaxed);
     let caller_reference = format!("list-of-lists-{distribution_id}-{nanos}-{counter}");
 
+    // The trailing wildcard also covers the precompressed index.html.br/.gz variants.
+    let items: Vec<String> = invalidation
+        .pages
+        .iter()
+        .map(|page| format!("/{page}*"))
+        .collect();
     let paths = Paths::builder()
-        .quantity(1)
-        .items("/index.html")
+        .quantity(items.len() as i32)
+        .set_items(Some(items.clone()))
         .build()
         .context("build invalidation Paths")?;
 
@@ -169,7 +305,10 @@ async fn invalidate_distribution(
         .build()
         .context("build InvalidationBatch")?;
 
-    info!("Invalidating /index.html on distribution {distribution_id} for sites {sites:?}");
+    info!(
+        "Invalidating {items:?} on distribution {distribution_id} for sites {:?}",
+        invalidation.sites
+    );
     client
         .create_invalidation()
         .distribution_id(distribution_id)
@@ -1,29 +1,90 @@
 use anyhow::{Result, anyhow};
+use caseless::Caseless;
+use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::LazyLock;
+use unicode_normalization::UnicodeNormalization;
 
+mod assets;
+pub mod canonical;
+pub mod convert;
+mod csp;
+mod export;
+mod feed;
+mod first_seen;
 pub mod generator;
+mod html;
+pub mod imports;
+mod include;
+mod locale;
+mod sort;
+pub mod sri;
+#[cfg(test)]
+mod test_util;
+mod theme;
 
 pub const APP_NAME: &str = "list_of_lists";
 
 pub const GENERATOR_BUCKET_VAR: &str = "LOL_GENERATOR_BUCKET";
 pub const SITE_URL_VAR: &str = "LOL_SITE_URL";
+pub const IMPORT_ORIGINS_VAR: &str = "LOL_IMPORT_ORIGINS";
+pub const REQUIRE_INTEGRITY_VAR: &str = "LOL_REQUIRE_INTEGRITY";
+pub const VENDOR_ASSETS_VAR: &str = "LOL_VENDOR_ASSETS";
+
+// The stylesheet index.template loads for legacy footer_links; migrated sites import it themselves.
+const BOOTSTRAP_ICONS_IMPORT: &str = r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/bootstrap-icons@1.13.1/font/bootstrap-icons.min.css" integrity="sha384-CK2SzKma4jA5H/MXDUU7i1TqZlCFaD4T01vtyDFvPlD97JQyS+IsSh1nI2EFbpyk" crossorigin="anonymous">"#;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub struct ListOfLists {
     pub title: String,
 
     // Used for the meta/OpenGraph descriptions; falls back to title in the template.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     pub lists: Vec<List>,
 
-    #[serde(default, alias = "footerLinks")]
+    #[serde(default, alias = "footerLinks", skip_serializing_if = "Vec::is_empty")]
     pub footer_links: Vec<FooterItem>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub footer: Option<Footer>,
+
+    // Extra Content-Security-Policy sources, keyed by directive, for origins the
+    // page loads indirectly (e.g. fonts fetched by an icon kit script).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub csp_sources: BTreeMap<String, Vec<String>>,
+
+    // How many days newly added items get a "new" badge; 0 disables badges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_item_days: Option<u32>,
+
+    // Copies of the visible lists in other formats to publish next to index.html.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exports: Vec<ExportFormat>,
+
+    // If set, items may only appear in one list (besides references), compared this way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unique_across_lists: Option<DuplicateMatch>,
+
+    // Language of the page and its built-in text, e.g. "es"; defaults to English.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+
+    // Other languages the site is also published in, as /<lang>/index.html, keyed
+    // by locale. Lists carry their own translations.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<String, SiteTranslation>,
+
+    // Colors, fonts and extra CSS; without it the page uses Bootstrap's light theme
+    // with a dark navbar and footer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
 }
 
 impl ListOfLists {
@@ -41,12 +102,49 @@ impl ListOfLists {
         if self.lists.is_empty() {
             return Err(anyhow!("ListOfLists must contain at least one list"));
         }
-        for l in &self.lists {
+        if let Some(mode) = self.unique_across_lists {
+            let duplicates = self.cross_list_duplicates(mode);
+            if !duplicates.is_empty() {
+                return Err(anyhow!(
+                    "Items appear in more than one list: {}",
+                    duplicates.join(", ")
+                ));
+            }
+        }
+        // Lists are checked with references standing in for their items, as they
+        // render, but the site is returned with its references in place.
+        let resolved = self.clone().resolved()?;
+        for l in &resolved.lists {
             l.validate()?;
         }
+        if let Some(footer) = &self.footer {
+            imports::validate_imports(&footer.imports)?;
+        }
+        for (directive, sources) in &self.csp_sources {
+            validate_csp_sources(directive, sources)?;
+        }
+        if let Some(locale) = &self.locale {
+            locale::validate_locale(locale)?;
+        }
+        if let Some(theme) = &self.theme {
+            theme::validate_theme(theme)?;
+        }
+        let mut exports = HashSet::new();
+        for format in &self.exports {
+            if !exports.insert(format) {
+                return Err(anyhow!("exports lists {format:?} more than once"));
+            }
+        }
 
-        // Visible list titles become HTML ids after sanitization; collisions would
-        // produce duplicate ids and broken tab navigation.
+        resolved.validate_div_ids()?;
+        resolved.validate_translations()?;
+
+        Ok(self)
+    }
+
+    // Visible list titles become HTML ids after sanitization; collisions would
+    // produce duplicate ids and broken tab navigation.
+    fn validate_div_ids(&self) -> Result<()> {
         let mut div_ids: HashMap<String, &str> = HashMap::new();
         for l in self.lists.iter().filter(|l| !l.hidden) {
             let div_id = generator::sanitized_div_id(l.title.as_str());
@@ -63,23 +161,310 @@ impl ListOfLists {
                 ));
             }
         }
+        Ok(())
+    }
+
+    // Languages must be supported and differ from the site's own; list translations
+    // must be for one of them and translate every item. Translated titles become
+    // HTML ids on their page too.
+    fn validate_translations(&self) -> Result<()> {
+        let default = locale::for_site(self).code;
+        for (lang, translation) in &self.translations {
+            locale::validate_locale(lang).map_err(|e| anyhow!("{e} in translations"))?;
+            if lang == default {
+                return Err(anyhow!(
+                    "translations must not include the site's own locale {lang:?}"
+                ));
+            }
+            if translation.title.trim().is_empty() {
+                return Err(anyhow!(
+                    "ListOfLists title must not be empty in the {lang:?} translation"
+                ));
+            }
+            if let Some(description) = &translation.description
+                && description.trim().is_empty()
+            {
+                return Err(anyhow!(
+                    "ListOfLists description must not be empty in the {lang:?} translation"
+                ));
+            }
+        }
+        for l in &self.lists {
+            for (lang, translation) in &l.translations {
+                if !self.translations.contains_key(lang) {
+                    return Err(anyhow!(
+                        "List {:?} has a {lang:?} translation, but the site's translations don't include {lang:?}",
+                        l.title
+                    ));
+                }
+                translation.validate(l).map_err(|e| {
+                    anyhow!("{e} in the {lang:?} translation of list {:?}", l.title)
+                })?;
+            }
+        }
+        for lang in self.translations.keys() {
+            self.clone()
+                .translated(lang)
+                .validate_div_ids()
+                .map_err(|e| anyhow!("{e} in the {lang:?} translation"))?;
+        }
+        Ok(())
+    }
+
+    // The site as published in `lang`: its translated text where there is some and
+    // the original elsewhere. Items keep their ids, tags and schedules; the result
+    // has no translations of its own.
+    pub fn translated(mut self, lang: &str) -> Self {
+        if let Some(translation) = std::mem::take(&mut self.translations).remove(lang) {
+            self.title = translation.title;
+            if translation.description.is_some() {
+                self.description = translation.description;
+            }
+        }
+        for l in &mut self.lists {
+            l.translate(lang);
+        }
+        self.locale = Some(lang.to_string());
+        self
+    }
+
+    // Items that appear in more than one list, compared as duplicate_match compares
+    // items within a list. References are how an item is meant to be shared, so
+    // they don't count.
+    fn cross_list_duplicates(&self, mode: DuplicateMatch) -> Vec<String> {
+        let mut first_list = HashMap::new();
+        let mut duplicates = Vec::new();
+        for (i, l) in self.lists.iter().enumerate() {
+            for item in &l.list {
+                if matches!(item, ListItem::Reference { .. }) {
+                    continue;
+                }
+                let first = *first_list.entry(mode.key(item)).or_insert(i);
+                if first != i {
+                    duplicates.push(format!(
+                        "{:?} in {:?} and {:?}",
+                        item.item(),
+                        self.lists[first].title,
+                        l.title
+                    ));
+                }
+            }
+        }
+        duplicates
+    }
 
+    // The site as rendered, with each {"ref": id} replaced by a copy of the item with
+    // that id. Ids are unique across the site; only detailed items have them, so
+    // references can't chain.
+    pub fn resolved(mut self) -> Result<Self> {
+        let mut items = HashMap::new();
+        for item in self.lists.iter().flat_map(|l| &l.list) {
+            if let Some(id) = item.id()
+                && items.insert(id.to_string(), item.clone()).is_some()
+            {
+                return Err(anyhow!("Item id {id:?} is used more than once"));
+            }
+        }
+        for l in &mut self.lists {
+            for item in &mut l.list {
+                if let ListItem::Reference { reference } = item {
+                    *item = items.get(reference.as_str()).cloned().ok_or_else(|| {
+                        anyhow!(
+                            "Item reference {reference:?} in list {:?} matches no item id",
+                            l.title
+                        )
+                    })?;
+                }
+            }
+        }
         Ok(self)
     }
+
+    // Like validate, but also returns warnings: things that render fine but are
+    // probably mistakes. Strict callers treat them as errors.
+    pub fn validate_with_warnings(self) -> Result<(Self, Vec<String>)> {
+        let list_of_lists = self.validate()?;
+        let warnings = list_of_lists.clone().resolved()?.warnings();
+        Ok((list_of_lists, warnings))
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.footer.is_some() && !self.footer_links.is_empty() {
+            warnings.push(
+                "footer_links is ignored because footer is set; `migrate` removes them".to_string(),
+            );
+        }
+        if self.lists.iter().all(|l| l.hidden) {
+            warnings.push("Every list is hidden, so the page has no tabs".to_string());
+        }
+        for l in &self.lists {
+            for lang in self.translations.keys() {
+                match l.translations.get(lang) {
+                    None => warnings.push(format!(
+                        "List {:?} has no {lang:?} translation, so it's shown untranslated",
+                        l.title
+                    )),
+                    Some(translation) => {
+                        for (i, (item, translated)) in
+                            l.list.iter().zip(&translation.list).enumerate()
+                        {
+                            if let (
+                                ListItem::Detailed {
+                                    tooltip: Some(_), ..
+                                },
+                                ItemTranslation::Item(_),
+                            ) = (item, translated)
+                            {
+                                warnings.push(format!(
+                                    "Item {} in list {:?} has a tooltip, but its {lang:?} translation doesn't",
+                                    i + 1,
+                                    l.title
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            if l.hidden && l.list.is_empty() {
+                warnings.push(format!("List {:?} is hidden and empty", l.title));
+            }
+            for item in &l.list {
+                let text = item.item();
+                if text.trim() != text {
+                    warnings.push(format!(
+                        "Item {text:?} in list {:?} has leading or trailing whitespace",
+                        l.title
+                    ));
+                }
+                if let ListItem::Detailed {
+                    tooltip: Some(tooltip),
+                    ..
+                } = item
+                    && tooltip.trim() == text.trim()
+                {
+                    warnings.push(format!(
+                        "Item {text:?} in list {:?} has a tooltip identical to its text",
+                        l.title
+                    ));
+                }
+            }
+        }
+        warnings
+    }
+
+    // Rewrites legacy footer_links as the footer that renders the same way: icons
+    // become full Bootstrap Icons classes and the icon stylesheet an import. With a
+    // footer already set the links were never shown, so they're dropped and the
+    // footer left as is. Returns whether anything changed.
+    pub fn migrate_footer_links(&mut self) -> bool {
+        if self.footer_links.is_empty() {
+            return false;
+        }
+        let links = std::mem::take(&mut self.footer_links);
+        if self.footer.is_none() {
+            self.footer = Some(Footer {
+                imports: vec![BOOTSTRAP_ICONS_IMPORT.to_string()],
+                links: links
+                    .into_iter()
+                    .map(|link| FooterItem {
+                        icon: format!("bi bi-{}", link.icon),
+                        ..link
+                    })
+                    .collect(),
+            });
+        }
+        true
+    }
+
+    // Drops lists and items whose publish_at/expire_at window doesn't include `now`.
+    // Runs after validation, so scheduled-out content still has to be well-formed,
+    // and fails rather than leave a page without lists or without any items to show.
+    pub fn visible_at(mut self, now: DateTime<Utc>) -> Result<Self> {
+        let showed_items = self.shows_items();
+        self.lists
+            .retain(|l| is_visible_at(l.publish_at, l.expire_at, now));
+        for l in &mut self.lists {
+            l.list.retain(|item| item.is_visible_at(now));
+        }
+        if self.lists.is_empty() {
+            return Err(anyhow!("No list is published at {now}"));
+        }
+        if showed_items && !self.shows_items() {
+            return Err(anyhow!("No visible list has an item published at {now}"));
+        }
+        Ok(self)
+    }
+
+    fn shows_items(&self) -> bool {
+        self.lists.iter().any(|l| !l.hidden && !l.list.is_empty())
+    }
+
+    // Applies each list's sort mode. random_at_build is seeded from the render
+    // time and list title, so a given --render-time preview is reproducible.
+    pub fn sorted(mut self, now: DateTime<Utc>) -> Self {
+        for l in &mut self.lists {
+            sort::sort_items(&mut l.list, l.sort, sort_seed(now, &l.title));
+        }
+        self
+    }
+
+    // Every publish_at/expire_at across lists and items; a render is only stale
+    // once one of these has passed.
+    pub fn visibility_boundaries(&self) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        self.lists.iter().flat_map(|l| {
+            [l.publish_at, l.expire_at]
+                .into_iter()
+                .chain(l.list.iter().flat_map(ListItem::schedule))
+                .flatten()
+        })
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub struct List {
     pub title: String,
 
-    #[serde(default)]
+    // Intro paragraph under the tab header; also feeds JSON-LD and share previews.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    // CSS class(es) for an icon, following the FooterItem.icon convention.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+
+    #[serde(default, skip_serializing_if = "is_default")]
     pub hidden: bool,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub duplicates: bool,
 
+    // When two items count as duplicates; only checked when duplicates is false.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub duplicate_match: DuplicateMatch,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub sort: SortMode,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub style: ListStyle,
+
+    // First number of an ordered list, as in <ol start>.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime<Utc>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_at: Option<DateTime<Utc>>,
+
     pub list: Vec<ListItem>,
+
+    // The list's text in the site's other languages, keyed by locale.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<String, ListTranslation>,
 }
 
 impl List {
@@ -87,25 +472,349 @@ impl List {
         if self.title.trim().is_empty() {
             return Err(anyhow!("List title must not be empty"));
         }
+        if let Some(description) = &self.description
+            && description.trim().is_empty()
+        {
+            return Err(anyhow!(
+                "List description must not be empty in list {:?}",
+                self.title
+            ));
+        }
+        if let Some(icon) = &self.icon
+            && icon.trim().is_empty()
+        {
+            return Err(anyhow!(
+                "List icon must not be empty in list {:?}",
+                self.title
+            ));
+        }
+        validate_schedule(self.publish_at, self.expire_at)
+            .map_err(|e| anyhow!("{} for list {:?}", e, self.title))?;
+        if self.start.is_some() && self.style != ListStyle::Ordered {
+            return Err(anyhow!(
+                "List {:?} sets start, which requires style \"ordered\"",
+                self.title
+            ));
+        }
         for item in &self.list {
             item.validate()
                 .map_err(|e| anyhow!("{} in list {:?}", e, self.title))?;
         }
-        if !self.duplicates && self.list.iter().collect::<HashSet<_>>().len() != self.list.len() {
-            return Err(anyhow!("Illegal duplicates found in {:?}", self.list));
+        if self.duplicates && self.duplicate_match != DuplicateMatch::Exact {
+            return Err(anyhow!(
+                "List {:?} sets duplicate_match, which requires duplicates to be false",
+                self.title
+            ));
+        }
+        if !self.duplicates {
+            let pairs = self.duplicate_pairs();
+            if !pairs.is_empty() {
+                let described: Vec<String> = pairs
+                    .iter()
+                    .map(|&(first, second)| {
+                        format!(
+                            "{} and {} ({:?} and {:?})",
+                            first + 1,
+                            second + 1,
+                            self.list[first].item(),
+                            self.list[second].item()
+                        )
+                    })
+                    .collect();
+                return Err(anyhow!(
+                    "List {:?} has duplicate items: {}",
+                    self.title,
+                    described.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Index pairs (first occurrence, later one) of items that match under duplicate_match.
+    fn duplicate_pairs(&self) -> Vec<(usize, usize)> {
+        let mut seen = HashMap::new();
+        let mut pairs = Vec::new();
+        for (i, item) in self.list.iter().enumerate() {
+            let key = self.duplicate_match.key(item);
+            match seen.get(&key) {
+                Some(&first) => pairs.push((first, i)),
+                None => {
+                    seen.insert(key, i);
+                }
+            }
+        }
+        pairs
+    }
+
+    // Distinct tags across the list's items, in order of first appearance.
+    pub fn tags(&self) -> Vec<&str> {
+        tags_of(&self.list)
+    }
+
+    fn translate(&mut self, lang: &str) {
+        let Some(translation) = std::mem::take(&mut self.translations).remove(lang) else {
+            return;
+        };
+        self.title = translation.title;
+        if translation.description.is_some() {
+            self.description = translation.description;
+        }
+        for (item, translated) in self.list.iter_mut().zip(translation.list) {
+            item.translate(translated);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct SiteTranslation {
+    pub title: String,
+
+    // Falls back to the site's own description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct ListTranslation {
+    pub title: String,
+
+    // Falls back to the list's own description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    // One per item of the list, in the same order.
+    pub list: Vec<ItemTranslation>,
+}
+
+impl ListTranslation {
+    fn validate(&self, list: &List) -> Result<()> {
+        if self.title.trim().is_empty() {
+            return Err(anyhow!("List title must not be empty"));
+        }
+        if let Some(description) = &self.description
+            && description.trim().is_empty()
+        {
+            return Err(anyhow!("List description must not be empty"));
+        }
+        if self.list.len() != list.list.len() {
+            return Err(anyhow!(
+                "List has {} items, but {} are translated",
+                list.list.len(),
+                self.list.len()
+            ));
+        }
+        for (i, translated) in self.list.iter().enumerate() {
+            let (item, tooltip) = match translated {
+                ItemTranslation::Item(item) => (item, None),
+                ItemTranslation::Detailed { item, tooltip } => (item, Some(tooltip)),
+            };
+            if item.trim().is_empty() {
+                return Err(anyhow!("Item {} must not be empty", i + 1));
+            }
+            if tooltip.is_some_and(|tooltip| tooltip.trim().is_empty()) {
+                return Err(anyhow!("Tooltip must not be empty for item {}", i + 1));
+            }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
-#[serde(untagged)]
+// An item's text, and its tooltip's if it has one. Untranslated tooltips are kept.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum ItemTranslation {
+    Item(String),
+    Detailed { item: String, tooltip: String },
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortMode {
+    #[default]
+    Source,
+    // Alpha, AlphaCi and Natural ignore combining accents but aren't locale-aware:
+    // letters that don't decompose (ß, ø, Æ) sort by code point after "z", and
+    // languages that order accented letters separately (Swedish Ä after Z) don't.
+    Alpha,
+    AlphaCi,
+    Natural,
+    Reverse,
+    RandomAtBuild,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateMatch {
+    // The whole item, tooltip, tags and schedule included. Every mode trims the text.
+    #[default]
+    Exact,
+    // Item text only.
+    ItemText,
+    // Item text after NFC normalization.
+    Nfc,
+    // Item text after NFKC normalization and full Unicode case folding, so "ß" matches
+    // "SS" and a final sigma matches any other.
+    CaseFolded,
+}
+
+#[derive(Hash, Eq, PartialEq)]
+enum DuplicateKey<'a> {
+    Item(Cow<'a, ListItem>),
+    Text(String),
+}
+
+impl DuplicateMatch {
+    fn key(self, item: &ListItem) -> DuplicateKey<'_> {
+        let text = item.item().trim();
+        match self {
+            DuplicateMatch::Exact if text == item.item() => DuplicateKey::Item(Cow::Borrowed(item)),
+            DuplicateMatch::Exact => {
+                let mut trimmed = item.clone();
+                if let ListItem::Item(item) | ListItem::Detailed { item, .. } = &mut trimmed {
+                    *item = text.to_string();
+                }
+                DuplicateKey::Item(Cow::Owned(trimmed))
+            }
+            DuplicateMatch::ItemText => DuplicateKey::Text(text.to_string()),
+            DuplicateMatch::Nfc => DuplicateKey::Text(text.nfc().collect()),
+            DuplicateMatch::CaseFolded => {
+                DuplicateKey::Text(text.nfkd().default_case_fold().nfkc().collect())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+    Text,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListStyle {
+    #[default]
+    Ordered,
+    Unordered,
+    Checklist,
+    Inline,
+}
+
+pub(crate) fn tags_of(items: &[ListItem]) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    items
+        .iter()
+        .flat_map(ListItem::tags)
+        .map(String::as_str)
+        .filter(|tag| seen.insert(*tag))
+        .collect()
+}
+
+// Object items reject unknown keys, so a misspelled field or a "ref" next to an
+// "item" fails to parse instead of being dropped.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
 pub enum ListItem {
     Item(String),
-    WithTooltip { item: String, tooltip: String },
+    Detailed {
+        item: String,
+
+        // Lets other lists reference this item with {"ref": id}.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tooltip: Option<String>,
+
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        publish_at: Option<DateTime<Utc>>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expire_at: Option<DateTime<Utc>>,
+    },
+    // Stands in for the item with this id, which may be in another list.
+    Reference {
+        #[serde(rename = "ref")]
+        reference: String,
+    },
 }
 
 impl ListItem {
+    // Only resolved() sites are free of references, whose id stands in for their text here.
+    pub fn item(&self) -> &str {
+        match self {
+            ListItem::Item(item) | ListItem::Detailed { item, .. } => item,
+            ListItem::Reference { reference } => reference,
+        }
+    }
+
+    pub fn tags(&self) -> &[String] {
+        match self {
+            ListItem::Item(_) | ListItem::Reference { .. } => &[],
+            ListItem::Detailed { tags, .. } => tags,
+        }
+    }
+
+    fn id(&self) -> Option<&str> {
+        match self {
+            ListItem::Detailed { id, .. } => id.as_deref(),
+            _ => None,
+        }
+    }
+
+    // Sites are resolved() by the time they're translated.
+    fn translate(&mut self, translation: ItemTranslation) {
+        let (text, translated_tooltip) = match translation {
+            ItemTranslation::Item(item) => (item, None),
+            ItemTranslation::Detailed { item, tooltip } => (item, Some(tooltip)),
+        };
+        match self {
+            ListItem::Item(item) if translated_tooltip.is_none() => *item = text,
+            ListItem::Item(_) => {
+                *self = ListItem::Detailed {
+                    item: text,
+                    id: None,
+                    tooltip: translated_tooltip,
+                    tags: Vec::new(),
+                    publish_at: None,
+                    expire_at: None,
+                }
+            }
+            ListItem::Detailed { item, tooltip, .. } => {
+                *item = text;
+                if translated_tooltip.is_some() {
+                    *tooltip = translated_tooltip;
+                }
+            }
+            ListItem::Reference { .. } => {}
+        }
+    }
+
+    fn schedule(&self) -> [Option<DateTime<Utc>>; 2] {
+        match self {
+            ListItem::Item(_) => [None, None],
+            ListItem::Detailed {
+                publish_at,
+                expire_at,
+                ..
+            } => [*publish_at, *expire_at],
+            ListItem::Reference { .. } => [None, None],
+        }
+    }
+
+    fn is_visible_at(&self, now: DateTime<Utc>) -> bool {
+        let [publish_at, expire_at] = self.schedule();
+        is_visible_at(publish_at, expire_at, now)
+    }
+
     fn validate(&self) -> Result<()> {
         match self {
             ListItem::Item(s) => {
@@ -113,39 +822,187 @@ impl ListItem {
                     return Err(anyhow!("List item must not be empty"));
                 }
             }
-            ListItem::WithTooltip { item, tooltip } => {
+            ListItem::Reference { reference } => validate_item_id(reference)?,
+            ListItem::Detailed {
+                item,
+                id,
+                tooltip,
+                tags,
+                publish_at,
+                expire_at,
+            } => {
                 if item.trim().is_empty() {
                     return Err(anyhow!("List item must not be empty"));
                 }
-                if tooltip.trim().is_empty() {
+                if let Some(id) = id {
+                    validate_item_id(id).map_err(|e| anyhow!("{} for item {:?}", e, item))?;
+                }
+                validate_schedule(*publish_at, *expire_at)
+                    .map_err(|e| anyhow!("{} for item {:?}", e, item))?;
+                if let Some(tooltip) = tooltip
+                    && tooltip.trim().is_empty()
+                {
                     return Err(anyhow!("Tooltip must not be empty for item {:?}", item));
                 }
+                let mut seen = HashSet::new();
+                for tag in tags {
+                    validate_tag(tag).map_err(|e| anyhow!("{} for item {:?}", e, item))?;
+                    if !seen.insert(tag) {
+                        return Err(anyhow!("Duplicate tag {:?} for item {:?}", tag, item));
+                    }
+                }
             }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Only host and scheme sources: keywords like 'unsafe-inline' would defeat the
+// generated hashes, and ';' or whitespace would inject extra directives.
+fn validate_csp_sources(directive: &str, sources: &[String]) -> Result<()> {
+    static SOURCE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^(?:(?:https?|wss?)://[A-Za-z0-9.*-]+(?::[0-9]+)?/?|[a-z][a-z0-9+.-]*:)$")
+            .unwrap()
+    });
+
+    if !csp::EXTENSIBLE_DIRECTIVES.contains(&directive) {
+        return Err(anyhow!(
+            "csp_sources directive {directive:?} must be one of {:?}",
+            csp::EXTENSIBLE_DIRECTIVES
+        ));
+    }
+    for source in sources {
+        if !SOURCE.is_match(source) {
+            return Err(anyhow!(
+                "csp_sources {directive:?} source {source:?} must be an origin like \"https://example.com\" or a scheme like \"data:\""
+            ));
+        }
+    }
+    Ok(())
+}
+
+// publish_at is inclusive and expire_at exclusive, so back-to-back windows never overlap.
+fn is_visible_at(
+    publish_at: Option<DateTime<Utc>>,
+    expire_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    publish_at.is_none_or(|p| p <= now) && expire_at.is_none_or(|e| now < e)
+}
+
+fn validate_schedule(
+    publish_at: Option<DateTime<Utc>>,
+    expire_at: Option<DateTime<Utc>>,
+) -> Result<()> {
+    if let (Some(publish_at), Some(expire_at)) = (publish_at, expire_at)
+        && publish_at >= expire_at
+    {
+        return Err(anyhow!(
+            "publish_at {publish_at} must be before expire_at {expire_at}"
+        ));
+    }
+    Ok(())
+}
+
+// Ids only appear in site JSON, but share the tag alphabet so they stay easy to type.
+fn validate_item_id(id: &str) -> Result<()> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "Item id {:?} must be non-empty and only contain letters, digits, '-' and '_'",
+            id
+        ));
+    }
+    Ok(())
+}
+
+// Tags are emitted space-separated in a data attribute and matched verbatim by
+// the filtering script, so whitespace or markup characters would split or
+// break them.
+fn validate_tag(tag: &str) -> Result<()> {
+    if tag.is_empty() {
+        return Err(anyhow!("Tag must not be empty"));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow!(
+            "Tag {:?} may only contain letters, digits, '-' and '_'",
+            tag
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub struct Footer {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub imports: Vec<String>,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub links: Vec<FooterItem>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub struct Theme {
+    // Hex color for links, the navbar and footer, and the browser's theme-color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary_color: Option<String>,
+
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub color_scheme: ColorScheme,
+
+    // CSS font-family list for the page's text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_family: Option<String>,
+
+    // Generator bucket key of a stylesheet inlined after the template's own styles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_css: Option<String>,
+}
+
+// Bootstrap's data-bs-theme; auto follows the viewer's prefers-color-scheme.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+    Auto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub struct FooterItem {
     pub url: String,
     pub icon: String,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
 }
 
+// SHA-256 rather than std's hashers, whose output may change between Rust releases
+// and reshuffle every site's random_at_build lists.
+fn sort_seed(now: DateTime<Utc>, title: &str) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(now.to_rfc3339())
+        .chain_update([0])
+        .chain_update(title)
+        .finalize()
+        .into()
+}
+
+// Lets fields left at their default stay out of the serialized site.
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 pub mod s3util {
     use super::*;
     use anyhow::Context;
@@ -171,9 +1028,52 @@ pub mod s3util {
             .await
             .with_context(|| format!("read body of {bucket_name}/{object_name}"))?
             .into_bytes();
-        debug!("Read {bucket_name}:{object_name} from S3");
+        debug!("Read {bucket_name}:{object_name} from S3");
+
+        Ok(bytes)
+    }
+
+    // Like get, but a missing object is None. S3 only reports missing objects as such
+    // (rather than as access denied) to callers with s3:ListBucket on the bucket.
+    pub async fn get_if_exists(
+        s3_client: &aws_sdk_s3::Client,
+        bucket_name: &str,
+        object_name: &str,
+    ) -> Result<Option<Bytes>> {
+        debug!("Reading {bucket_name}:{object_name} from S3 if it exists");
+        let output = match s3_client
+            .get_object()
+            .bucket(bucket_name)
+            .key(object_name)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => {
+                debug!("{bucket_name}:{object_name} does not exist");
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("get_object {bucket_name}/{object_name}"));
+            }
+        };
+        let bytes = output
+            .body
+            .collect()
+            .await
+            .with_context(|| format!("read body of {bucket_name}/{object_name}"))?
+            .into_bytes();
 
-        Ok(bytes)
+        Ok(Some(bytes))
+    }
+
+    // Headers S3 stores with an object and serves back on GET.
+    #[derive(Debug, Default)]
+    pub struct ObjectHeaders {
+        pub cache_control: Option<String>,
+        pub content_encoding: Option<String>,
+        // Sent as x-amz-meta-<key>.
+        pub metadata: HashMap<String, String>,
     }
 
     pub async fn put(
@@ -181,6 +1081,7 @@ pub mod s3util {
         bucket_name: &str,
         object_name: &str,
         content_type: &str,
+        headers: &ObjectHeaders,
         data: Vec<u8>,
     ) -> Result<()> {
         debug!("Uploading {bucket_name}:{object_name} to S3");
@@ -189,6 +1090,9 @@ pub mod s3util {
             .bucket(bucket_name)
             .key(object_name)
             .content_type(content_type)
+            .set_cache_control(headers.cache_control.clone())
+            .set_content_encoding(headers.content_encoding.clone())
+            .set_metadata(Some(headers.metadata.clone()).filter(|m| !m.is_empty()))
             .body(ByteStream::from(Bytes::from(data)))
             .send()
             .await
@@ -198,6 +1102,23 @@ pub mod s3util {
         Ok(())
     }
 
+    pub async fn delete(
+        s3_client: &aws_sdk_s3::Client,
+        bucket_name: &str,
+        object_name: &str,
+    ) -> Result<()> {
+        debug!("Deleting {bucket_name}:{object_name} from S3");
+        s3_client
+            .delete_object()
+            .bucket(bucket_name)
+            .key(object_name)
+            .send()
+            .await
+            .with_context(|| format!("delete_object {bucket_name}/{object_name}"))?;
+
+        Ok(())
+    }
+
     pub async fn list_keys(
         s3_client: &aws_sdk_s3::Client,
         bucket_name: &str,
@@ -273,7 +1194,7 @@ mod test {
     "#;
 
     impl List {
-        fn new(title: &str, hidden: bool, duplicates: bool, list: &[&str]) -> Self {
+        pub(crate) fn new(title: &str, hidden: bool, duplicates: bool, list: &[&str]) -> Self {
             let list_items: Vec<ListItem> = list.iter().cloned().map(ListItem::new).collect();
             Self::from_items(title, hidden, duplicates, list_items)
         }
@@ -281,9 +1202,18 @@ mod test {
         fn from_items(title: &str, hidden: bool, duplicates: bool, list: Vec<ListItem>) -> Self {
             Self {
                 title: title.to_string(),
+                description: None,
+                icon: None,
                 hidden,
                 duplicates,
+                duplicate_match: DuplicateMatch::Exact,
+                sort: SortMode::Source,
+                style: ListStyle::Ordered,
+                start: None,
+                publish_at: None,
+                expire_at: None,
                 list,
+                translations: BTreeMap::new(),
             }
         }
     }
@@ -294,17 +1224,71 @@ mod test {
         }
 
         fn with_tooltip(item: &str, tooltip: &str) -> Self {
-            ListItem::WithTooltip {
+            ListItem::Detailed {
+                item: item.to_string(),
+                id: None,
+                tooltip: Some(tooltip.to_string()),
+                tags: vec![],
+                publish_at: None,
+                expire_at: None,
+            }
+        }
+
+        fn with_tags(item: &str, tags: &[&str]) -> Self {
+            ListItem::Detailed {
+                item: item.to_string(),
+                id: None,
+                tooltip: None,
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                publish_at: None,
+                expire_at: None,
+            }
+        }
+
+        fn scheduled(item: &str, publish_at: Option<&str>, expire_at: Option<&str>) -> Self {
+            ListItem::Detailed {
                 item: item.to_string(),
-                tooltip: tooltip.to_string(),
+                id: None,
+                tooltip: None,
+                tags: vec![],
+                publish_at: publish_at.map(timestamp),
+                expire_at: expire_at.map(timestamp),
             }
         }
     }
 
+    pub(crate) fn timestamp(s: &str) -> DateTime<Utc> {
+        s.parse().expect("test timestamps must be RFC 3339")
+    }
+
+    pub(crate) fn site(lists: Vec<List>) -> ListOfLists {
+        ListOfLists {
+            title: "The List".to_string(),
+            description: None,
+            footer_links: vec![],
+            footer: None,
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
+            lists,
+        }
+    }
+
     #[test]
     fn test_list_of_lists_serde() -> Result<()> {
         let list_of_lists = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             footer_links: vec![],
             description: None,
             footer: None,
@@ -339,8 +1323,228 @@ mod test {
 
     #[test]
     fn test_list_validation_duplicates_disallowed() {
-        let l = List::new("Letters", false, false, &["A", "A"]);
+        let l = List::new("Letters", false, false, &["A", "B", "A"]);
+
+        assert_eq!(
+            "List \"Letters\" has duplicate items: 1 and 3 (\"A\" and \"A\")",
+            l.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_item_references() -> Result<()> {
+        let lol: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [
+                {"title": "Dinner", "list": [{"item": "Soup", "id": "soup", "tooltip": "Hot"}, "Bread"]},
+                {"title": "Lunch", "list": ["Salad", {"ref": "soup"}]}
+            ]}"#,
+        )?;
+        assert_eq!(
+            ListItem::Reference {
+                reference: "soup".to_string()
+            },
+            lol.lists[1].list[1]
+        );
+
+        // Validation leaves the reference for the site's JSON; rendering resolves it.
+        let lol = lol.validate()?;
+        assert!(matches!(lol.lists[1].list[1], ListItem::Reference { .. }));
+        let lol = lol.resolved()?;
+        assert_eq!(lol.lists[0].list[0], lol.lists[1].list[1]);
+        assert_eq!(Some("soup"), lol.lists[1].list[1].id());
+
+        let missing = site(vec![List::from_items(
+            "Lunch",
+            false,
+            false,
+            vec![ListItem::Reference {
+                reference: "soup".to_string(),
+            }],
+        )]);
+        assert_eq!(
+            "Item reference \"soup\" in list \"Lunch\" matches no item id",
+            missing.validate().unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    const TRANSLATED_SITE: &str = r#"{"title": "Food", "translations": {"es": {"title": "Comida"}},
+        "lists": [
+            {"title": "Dinner", "description": "Evening meals",
+             "list": [{"item": "Soup", "tooltip": "Hot", "tags": ["warm"]}, "Bread"],
+             "translations": {"es": {"title": "Cena", "list": ["Sopa", {"item": "Pan", "tooltip": "Tierno"}]}}},
+            {"title": "Lunch", "list": ["Salad"]}
+        ]}"#;
+
+    #[test]
+    fn test_translated() -> Result<()> {
+        let (lol, warnings) =
+            serde_json::from_str::<ListOfLists>(TRANSLATED_SITE)?.validate_with_warnings()?;
+        assert_eq!(
+            vec![
+                r#"Item 1 in list "Dinner" has a tooltip, but its "es" translation doesn't"#,
+                r#"List "Lunch" has no "es" translation, so it's shown untranslated"#,
+            ],
+            warnings
+        );
+
+        let es = lol.translated("es");
+        assert_eq!("Comida", es.title);
+        assert_eq!(Some("es"), es.locale.as_deref());
+        assert!(es.translations.is_empty());
+        let dinner = &es.lists[0];
+        assert_eq!("Cena", dinner.title);
+        // Untranslated text falls back to the original.
+        assert_eq!(Some("Evening meals"), dinner.description.as_deref());
+        assert_eq!(
+            ListItem::Detailed {
+                item: "Sopa".to_string(),
+                id: None,
+                tooltip: Some("Hot".to_string()),
+                tags: vec!["warm".to_string()],
+                publish_at: None,
+                expire_at: None,
+            },
+            dinner.list[0]
+        );
+        assert!(matches!(
+            &dinner.list[1],
+            ListItem::Detailed { item, tooltip: Some(tooltip), .. } if item == "Pan" && tooltip == "Tierno"
+        ));
+        assert_eq!("Lunch", es.lists[1].title);
+        Ok(())
+    }
+
+    #[test]
+    fn test_translation_validation() -> Result<()> {
+        let invalid = |edit: fn(&mut ListOfLists)| {
+            let mut lol: ListOfLists = serde_json::from_str(TRANSLATED_SITE).unwrap();
+            edit(&mut lol);
+            lol.validate().unwrap_err().to_string()
+        };
+        assert_eq!(
+            r#"List has 2 items, but 1 are translated in the "es" translation of list "Dinner""#,
+            invalid(|lol| {
+                lol.lists[0].translations.get_mut("es").unwrap().list.pop();
+            })
+        );
+        assert_eq!(
+            r#"List "Dinner" has a "fr" translation, but the site's translations don't include "fr""#,
+            invalid(|lol| {
+                let es = lol.lists[0].translations["es"].clone();
+                lol.lists[0].translations.insert("fr".to_string(), es);
+            })
+        );
+        assert_eq!(
+            r#"translations must not include the site's own locale "es""#,
+            invalid(|lol| lol.locale = Some("es".to_string()))
+        );
+        assert_eq!(
+            r#"locale "xx" must be one of ["en", "es", "fr", "de", "it", "pt", "ar", "he"] in translations"#,
+            invalid(|lol| {
+                let es = lol.translations["es"].clone();
+                lol.translations.insert("xx".to_string(), es);
+            })
+        );
+        assert_eq!(
+            r#"List titles "Lunch" and "Lunch" both map to HTML id "Lunch" in the "es" translation"#,
+            invalid(|lol| {
+                lol.lists[0].translations.get_mut("es").unwrap().title = "Lunch".to_string();
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_item_ids_must_be_unique() -> Result<()> {
+        let lol: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [
+                {"title": "Dinner", "list": [{"item": "Soup", "id": "soup"}]},
+                {"title": "Lunch", "list": [{"item": "Stew", "id": "soup"}]}
+            ]}"#,
+        )?;
+        assert!(lol.validate().is_err());
+
+        let lol: ListOfLists = serde_json::from_str(
+            r#"{"title": "Food", "lists": [{"title": "Dinner", "list": [{"item": "Soup", "id": "hot soup"}]}]}"#,
+        )?;
+        assert!(lol.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_across_lists() -> Result<()> {
+        let json = r#"{"title": "Food", "lists": [
+            {"title": "Dinner", "list": [{"item": "Soup", "id": "soup"}, "Bread"]},
+            {"title": "Lunch", "list": ["bread ", {"ref": "soup"}]},
+            {"title": "Breakfast", "list": ["Eggs"]}
+        ]}"#;
+
+        // Off by default, and references never count.
+        let mut lol: ListOfLists = serde_json::from_str(json)?;
+        assert!(lol.cross_list_duplicates(DuplicateMatch::Exact).is_empty());
+        assert!(lol.validate().is_ok());
+
+        lol = serde_json::from_str(json)?;
+        lol.unique_across_lists = Some(DuplicateMatch::CaseFolded);
+        assert_eq!(
+            "Items appear in more than one list: \"bread \" in \"Dinner\" and \"Lunch\"",
+            lol.validate().unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_validation_duplicate_match() {
+        // "Café" precomposed and with a combining accent.
+        let mut l = List::from_items(
+            "Cafés",
+            false,
+            false,
+            vec![
+                ListItem::new("Caf\u{e9}"),
+                ListItem::with_tooltip("Bar", "Open late"),
+                ListItem::new("Cafe\u{301} "),
+                ListItem::new("bar"),
+            ],
+        );
+        assert!(l.validate().is_ok());
+
+        l.duplicate_match = DuplicateMatch::ItemText;
+        assert!(l.validate().is_ok());
+
+        l.duplicate_match = DuplicateMatch::Nfc;
+        assert_eq!(vec![(0, 2)], l.duplicate_pairs());
 
+        l.duplicate_match = DuplicateMatch::CaseFolded;
+        assert_eq!(vec![(0, 2), (1, 3)], l.duplicate_pairs());
+
+        l.list[3] = ListItem::new("Bar");
+        l.duplicate_match = DuplicateMatch::ItemText;
+        assert_eq!(vec![(1, 3)], l.duplicate_pairs());
+
+        // Full case folding, not just lowercasing.
+        l.duplicate_match = DuplicateMatch::CaseFolded;
+        l.list = vec![
+            ListItem::new("Stra\u{df}e"),
+            ListItem::new("STRASSE"),
+            ListItem::new("strasse"),
+        ];
+        assert_eq!(vec![(0, 1), (0, 2)], l.duplicate_pairs());
+        l.list = vec![
+            ListItem::new("\u{3cc}\u{3c3}\u{3bf}\u{3c2}"),
+            ListItem::new("\u{38c}\u{3a3}\u{39f}\u{3a3}"),
+        ];
+        assert_eq!(vec![(0, 1)], l.duplicate_pairs());
+
+        // Every mode trims.
+        l.list = vec![ListItem::new("Bar"), ListItem::new(" Bar ")];
+        for duplicate_match in [DuplicateMatch::Exact, DuplicateMatch::ItemText] {
+            l.duplicate_match = duplicate_match;
+            assert_eq!(vec![(0, 1)], l.duplicate_pairs());
+        }
+
+        l.duplicates = true;
         assert!(l.validate().is_err());
     }
 
@@ -348,6 +1552,13 @@ mod test {
     fn test_validation_rejects_empty_top_level_title() {
         let lol = ListOfLists {
             title: "  ".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             footer_links: vec![],
             description: None,
             footer: None,
@@ -360,6 +1571,13 @@ mod test {
     fn test_validation_rejects_empty_lists_vec() {
         let lol = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             footer_links: vec![],
             description: None,
             footer: None,
@@ -372,6 +1590,13 @@ mod test {
     fn test_validation_rejects_blank_description() {
         let lol = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             description: Some("  ".to_string()),
             footer_links: vec![],
             footer: None,
@@ -384,6 +1609,13 @@ mod test {
     fn test_validation_rejects_colliding_div_ids() {
         let lol = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             description: None,
             footer_links: vec![],
             footer: None,
@@ -399,6 +1631,13 @@ mod test {
     fn test_validation_allows_hidden_div_id_collision() {
         let lol = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             description: None,
             footer_links: vec![],
             footer: None,
@@ -414,6 +1653,13 @@ mod test {
     fn test_validation_rejects_unusable_div_id() {
         let lol = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             description: None,
             footer_links: vec![],
             footer: None,
@@ -456,10 +1702,336 @@ mod test {
         assert!(l.validate().is_err());
     }
 
+    #[test]
+    fn test_list_item_tags_serde() -> Result<()> {
+        let item: ListItem = serde_json::from_str(r#"{"item": "Soup", "tags": ["veg", "cheap"]}"#)?;
+        assert_eq!(ListItem::with_tags("Soup", &["veg", "cheap"]), item);
+
+        let serialized = serde_json::to_string(&item)?;
+        assert_eq!(r#"{"item":"Soup","tags":["veg","cheap"]}"#, serialized);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_item_rejects_unknown_keys() {
+        for item in [
+            r#"{"item": "Soup", "tooltp": "Hot"}"#,
+            r#"{"item": "Soup", "ref": "soup"}"#,
+            r#"{"ref": "soup", "tooltip": "Hot"}"#,
+        ] {
+            assert!(serde_json::from_str::<ListItem>(item).is_err(), "{item}");
+        }
+        assert!(
+            serde_json::from_str::<ItemTranslation>(r#"{"item": "Sopa", "tip": "x"}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_list_tags_in_first_appearance_order() {
+        let l = List::from_items(
+            "Food",
+            false,
+            false,
+            vec![
+                ListItem::with_tags("Soup", &["veg", "cheap"]),
+                ListItem::new("Bread"),
+                ListItem::with_tags("Steak", &["meat", "cheap"]),
+            ],
+        );
+        assert_eq!(vec!["veg", "cheap", "meat"], l.tags());
+    }
+
+    #[test]
+    fn test_validation_rejects_invalid_tag() {
+        for tag in ["", "two words", "<b>"] {
+            let l = List::from_items(
+                "Food",
+                false,
+                false,
+                vec![ListItem::with_tags("Soup", &[tag])],
+            );
+            assert!(l.validate().is_err(), "{tag:?}");
+        }
+    }
+
+    #[test]
+    fn test_validation_rejects_duplicate_tag() {
+        let l = List::from_items(
+            "Food",
+            false,
+            false,
+            vec![ListItem::with_tags("Soup", &["veg", "veg"])],
+        );
+        assert!(l.validate().is_err());
+    }
+
+    #[test]
+    fn test_schedule_serde() -> Result<()> {
+        let l: List = serde_json::from_str(
+            r#"{"title": "Holiday", "publish_at": "2025-12-01T00:00:00-05:00", "list": [
+                {"item": "Socks", "expire_at": "2025-12-26T00:00:00Z"}
+            ]}"#,
+        )?;
+
+        assert_eq!(Some(timestamp("2025-12-01T05:00:00Z")), l.publish_at);
+        assert_eq!(None, l.expire_at);
+        assert_eq!(
+            vec![ListItem::scheduled(
+                "Socks",
+                None,
+                Some("2025-12-26T00:00:00Z")
+            )],
+            l.list
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation_rejects_inverted_schedule() {
+        let mut l = List::new("Holiday", false, false, &["Socks"]);
+        l.publish_at = Some(timestamp("2025-12-26T00:00:00Z"));
+        l.expire_at = Some(timestamp("2025-12-01T00:00:00Z"));
+        assert!(l.validate().is_err());
+
+        let l = List::from_items(
+            "Holiday",
+            false,
+            false,
+            vec![ListItem::scheduled(
+                "Socks",
+                Some("2025-12-01T00:00:00Z"),
+                Some("2025-12-01T00:00:00Z"),
+            )],
+        );
+        assert!(l.validate().is_err());
+    }
+
+    fn holiday_site() -> ListOfLists {
+        let mut holiday = List::from_items(
+            "Holiday",
+            false,
+            false,
+            vec![
+                ListItem::new("Socks"),
+                ListItem::scheduled("Tree", Some("2025-12-10T00:00:00Z"), None),
+                ListItem::scheduled("Pumpkin", None, Some("2025-12-01T00:00:00Z")),
+            ],
+        );
+        holiday.publish_at = Some(timestamp("2025-12-01T00:00:00Z"));
+        holiday.expire_at = Some(timestamp("2025-12-26T00:00:00Z"));
+        site(vec![List::new("Always", false, false, &["A"]), holiday])
+    }
+
+    #[test]
+    fn test_visible_at_filters_lists_and_items() {
+        let before = holiday_site()
+            .visible_at(timestamp("2025-11-30T23:59:59Z"))
+            .unwrap();
+        assert_eq!(
+            vec![List::new("Always", false, false, &["A"])],
+            before.lists
+        );
+
+        let opening = holiday_site()
+            .visible_at(timestamp("2025-12-01T00:00:00Z"))
+            .unwrap();
+        assert_eq!(2, opening.lists.len());
+        assert_eq!(vec![ListItem::new("Socks")], opening.lists[1].list);
+
+        let later = holiday_site()
+            .visible_at(timestamp("2025-12-10T00:00:00Z"))
+            .unwrap();
+        assert_eq!(2, later.lists[1].list.len());
+
+        let after = holiday_site()
+            .visible_at(timestamp("2025-12-26T00:00:00Z"))
+            .unwrap();
+        assert_eq!(1, after.lists.len());
+    }
+
+    #[test]
+    fn test_visible_at_keeps_something_to_show() {
+        let holiday_only = || {
+            let mut site = holiday_site();
+            site.lists.remove(0);
+            site
+        };
+        assert_eq!(
+            "No list is published at 2025-12-26 00:00:00 UTC",
+            holiday_only()
+                .visible_at(timestamp("2025-12-26T00:00:00Z"))
+                .unwrap_err()
+                .to_string()
+        );
+
+        let mut scheduled_items = holiday_only();
+        scheduled_items.lists[0].publish_at = None;
+        scheduled_items.lists[0].list.remove(0);
+        assert_eq!(
+            "No visible list has an item published at 2025-12-01 00:00:00 UTC",
+            scheduled_items
+                .visible_at(timestamp("2025-12-01T00:00:00Z"))
+                .unwrap_err()
+                .to_string()
+        );
+
+        // A site that shows no items anyway isn't made worse by scheduling.
+        let empty = site(vec![List::new("Empty", false, false, &[])]);
+        assert!(empty.visible_at(timestamp("2025-12-01T00:00:00Z")).is_ok());
+    }
+
+    #[test]
+    fn test_visibility_boundaries() {
+        let boundaries: Vec<_> = holiday_site().visibility_boundaries().collect();
+        assert_eq!(
+            vec![
+                timestamp("2025-12-01T00:00:00Z"),
+                timestamp("2025-12-26T00:00:00Z"),
+                timestamp("2025-12-10T00:00:00Z"),
+                timestamp("2025-12-01T00:00:00Z"),
+            ],
+            boundaries
+        );
+    }
+
+    #[test]
+    fn test_sort_serde() -> Result<()> {
+        let l: List = serde_json::from_str(r#"{"title": "Letters", "list": ["b", "a"]}"#)?;
+        assert_eq!(SortMode::Source, l.sort);
+
+        let l: List = serde_json::from_str(
+            r#"{"title": "Letters", "sort": "random_at_build", "list": ["b", "a"]}"#,
+        )?;
+        assert_eq!(SortMode::RandomAtBuild, l.sort);
+
+        assert!(
+            serde_json::from_str::<List>(r#"{"title": "Letters", "sort": "bogus", "list": []}"#)
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation_rejects_blank_list_description_and_icon() {
+        let mut l = List::new("Letters", false, false, &["A"]);
+        l.description = Some(" ".to_string());
+        assert!(l.validate().is_err());
+
+        let mut l = List::new("Letters", false, false, &["A"]);
+        l.icon = Some("".to_string());
+        assert!(l.validate().is_err());
+
+        let mut l = List::new("Letters", false, false, &["A"]);
+        l.description = Some("The alphabet".to_string());
+        l.icon = Some("bi bi-alphabet".to_string());
+        assert!(l.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validation_of_csp_sources() {
+        let with_sources = |directive: &str, source: &str| {
+            let mut lol = site(vec![List::new("Letters", false, false, &["A"])]);
+            lol.csp_sources = BTreeMap::from([(directive.to_string(), vec![source.to_string()])]);
+            lol.validate()
+        };
+
+        assert!(with_sources("font-src", "https://ka-f.fontawesome.com").is_ok());
+        assert!(with_sources("img-src", "blob:").is_ok());
+        assert!(with_sources("connect-src", "wss://*.example.com:8443").is_ok());
+        assert!(with_sources("frame-src", "https://example.com").is_err());
+        assert!(with_sources("script-src", "'unsafe-inline'").is_err());
+        assert!(with_sources("script-src", "https://a.com; object-src *").is_err());
+    }
+
+    #[test]
+    fn test_validation_of_exports() {
+        let mut lol = site(vec![List::new("Letters", false, false, &["A"])]);
+        lol.exports = vec![ExportFormat::Json];
+        let mut lol = lol.validate().unwrap();
+
+        lol.exports.push(ExportFormat::Json);
+        assert!(lol.validate().is_err());
+    }
+
+    #[test]
+    fn test_style_serde() -> Result<()> {
+        let l: List = serde_json::from_str(r#"{"title": "Letters", "list": ["a"]}"#)?;
+        assert_eq!(ListStyle::Ordered, l.style);
+        assert_eq!(None, l.start);
+
+        let l: List = serde_json::from_str(
+            r#"{"title": "Letters", "style": "ordered", "start": 5, "list": ["a"]}"#,
+        )?;
+        assert_eq!(Some(5), l.start);
+
+        let l: List =
+            serde_json::from_str(r#"{"title": "Letters", "style": "checklist", "list": ["a"]}"#)?;
+        assert_eq!(ListStyle::Checklist, l.style);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation_rejects_start_on_unordered_styles() {
+        for style in [
+            ListStyle::Unordered,
+            ListStyle::Checklist,
+            ListStyle::Inline,
+        ] {
+            let mut l = List::new("Letters", false, false, &["A"]);
+            l.style = style;
+            l.start = Some(3);
+            assert!(l.validate().is_err(), "{style:?}");
+        }
+
+        let mut l = List::new("Letters", false, false, &["A"]);
+        l.start = Some(3);
+        assert!(l.validate().is_ok());
+    }
+
+    #[test]
+    fn test_sorted_sorts_object_items_by_item_text() {
+        let mut l = List::from_items(
+            "Food",
+            false,
+            false,
+            vec![
+                ListItem::with_tags("Steak", &["meat"]),
+                ListItem::new("bread"),
+                ListItem::with_tooltip("Apple", "crunchy"),
+            ],
+        );
+        l.sort = SortMode::AlphaCi;
+
+        let sorted = site(vec![l]).sorted(timestamp("2025-12-01T00:00:00Z"));
+        let items: Vec<&str> = sorted.lists[0].list.iter().map(ListItem::item).collect();
+        assert_eq!(vec!["Apple", "bread", "Steak"], items);
+    }
+
+    #[test]
+    fn test_sort_seed_is_stable() {
+        // Pinned, so a change of hash shows up here rather than as reshuffled sites.
+        let seed = sort_seed(timestamp("2026-01-01T00:00:00Z"), "Letters");
+        assert_eq!(
+            "6df6d13fd51352dd6f2213eeac7da112c23a13396b3943249203ca2206db4b82",
+            seed.iter().map(|b| format!("{b:02x}")).collect::<String>()
+        );
+    }
+
     #[test]
     fn test_list_of_lists_footer() -> Result<()> {
         let list_of_lists = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             footer_links: vec![],
             description: None,
             footer: Some(Footer {
@@ -481,10 +2053,39 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_validation_rejects_unsafe_footer_imports() {
+        let with_import = |import: &str| {
+            let mut lol = site(vec![List::new("Letters", false, false, &["A"])]);
+            lol.footer = Some(Footer {
+                imports: vec![import.to_string()],
+                links: vec![],
+            });
+            lol.validate()
+        };
+
+        assert!(
+            with_import(r#"<script src="https://kit.fontawesome.com/deadbeef.js" crossorigin="anonymous"></script>"#)
+                .is_ok()
+        );
+        let e = with_import("<script>alert(1)</script>")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("footer.imports[0]"), "{e}");
+        assert!(e.contains("inline scripts"), "{e}");
+    }
+
     #[test]
     fn test_list_of_lists_legacy_footer() -> Result<()> {
         let list_of_lists = ListOfLists {
             title: "The List".to_string(),
+            csp_sources: BTreeMap::new(),
+            new_item_days: None,
+            exports: Vec::new(),
+            unique_across_lists: None,
+            locale: None,
+            translations: BTreeMap::new(),
+            theme: None,
             footer_links: vec![FooterItem {
                 url: "https://github.com".to_string(),
                 icon: "github".to_string(),
@@ -502,4 +2103,104 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_warnings() -> Result<()> {
+        let (_, warnings) =
+            site(vec![List::new("Letters", false, false, &["A"])]).validate_with_warnings()?;
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        let mut lol = site(vec![
+            List::new("Letters", false, false, &[" A"]),
+            List::new("Empty", true, false, &[]),
+        ]);
+        lol.lists[0].list.push(ListItem::Detailed {
+            item: "B".to_string(),
+            id: None,
+            tooltip: Some("B ".to_string()),
+            tags: vec![],
+            publish_at: None,
+            expire_at: None,
+        });
+        lol.footer = Some(Footer {
+            imports: vec![],
+            links: vec![],
+        });
+        lol.footer_links = vec![FooterItem {
+            url: "https://github.com".to_string(),
+            icon: "github".to_string(),
+            title: None,
+        }];
+        let (_, warnings) = lol.validate_with_warnings()?;
+        assert_eq!(
+            vec![
+                "footer_links is ignored because footer is set; `migrate` removes them",
+                r#"Item " A" in list "Letters" has leading or trailing whitespace"#,
+                r#"Item "B" in list "Letters" has a tooltip identical to its text"#,
+                r#"List "Empty" is hidden and empty"#,
+            ],
+            warnings
+        );
+
+        let (_, warnings) =
+            site(vec![List::new("Letters", true, false, &["A"])]).validate_with_warnings()?;
+        assert_eq!(
+            vec!["Every list is hidden, so the page has no tabs"],
+            warnings
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_footer_links() {
+        let mut lol = site(vec![List::new("Letters", false, false, &["A"])]);
+        assert!(!lol.migrate_footer_links());
+        assert_eq!(None, lol.footer);
+
+        lol.footer_links = vec![FooterItem {
+            url: "https://github.com".to_string(),
+            icon: "github".to_string(),
+            title: Some("GitHub".to_string()),
+        }];
+        assert!(lol.migrate_footer_links());
+        assert!(lol.footer_links.is_empty());
+        assert_eq!(
+            Some(Footer {
+                imports: vec![BOOTSTRAP_ICONS_IMPORT.to_string()],
+                links: vec![FooterItem {
+                    url: "https://github.com".to_string(),
+                    icon: "bi bi-github".to_string(),
+                    title: Some("GitHub".to_string()),
+                }],
+            }),
+            lol.footer
+        );
+        assert!(lol.clone().validate().is_ok());
+
+        // Links a footer already overrode are dropped, not merged in.
+        let footer = lol.footer.clone();
+        lol.footer_links = vec![FooterItem {
+            url: "https://example.com".to_string(),
+            icon: "house".to_string(),
+            title: None,
+        }];
+        assert!(lol.migrate_footer_links());
+        assert!(lol.footer_links.is_empty());
+        assert_eq!(footer, lol.footer);
+    }
+
+    #[test]
+    fn test_bootstrap_icons_import_matches_template() {
+        let template = include_str!("../index.template");
+        let attrs = |tag: &str| {
+            let mut attrs = html::attributes(tag);
+            attrs.retain(|(name, _)| name == "href" || name == "integrity");
+            attrs
+        };
+        let legacy = template
+            .lines()
+            .find(|line| line.contains("<noscript><link") && line.contains("bootstrap-icons"))
+            .expect("index.template loads Bootstrap Icons for footer_links");
+        assert_eq!(attrs(legacy), attrs(BOOTSTRAP_ICONS_IMPORT));
+    }
 }
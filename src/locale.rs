@@ -0,0 +1,223 @@
+use crate::ListOfLists;
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+// The page's language when a site doesn't set locale.
+pub(crate) const DEFAULT_LOCALE: &str = "en";
+
+// The template's own text, as opposed to the site's. `{list}`, `{date}` and `{title}`
+// are filled in by the template.
+#[derive(Debug, Serialize)]
+pub(crate) struct UiStrings {
+    main_navigation: &'static str,
+    toggle_navigation: &'static str,
+    list_categories: &'static str,
+    list_content: &'static str,
+    filter_by_tag: &'static str,
+    new: &'static str,
+    added: &'static str,
+    site_footer: &'static str,
+    feed_title: &'static str,
+    languages: &'static str,
+}
+
+#[derive(Debug)]
+pub(crate) struct Locale {
+    // BCP 47 primary language subtag, as used for <html lang>.
+    pub(crate) code: &'static str,
+    // The language's own name for itself, for language links.
+    pub(crate) name: &'static str,
+    pub(crate) rtl: bool,
+    pub(crate) ui: UiStrings,
+}
+
+impl Locale {
+    pub(crate) fn dir(&self) -> &'static str {
+        if self.rtl { "rtl" } else { "ltr" }
+    }
+}
+
+static LOCALES: [Locale; 8] = [
+    Locale {
+        code: "en",
+        name: "English",
+        rtl: false,
+        ui: UiStrings {
+            main_navigation: "Main navigation",
+            toggle_navigation: "Toggle navigation menu",
+            list_categories: "List categories",
+            list_content: "List content",
+            filter_by_tag: "Filter {list} by tag",
+            new: "New",
+            added: "Added {date}",
+            site_footer: "Site footer",
+            feed_title: "{title}: what's changed",
+            languages: "Languages",
+        },
+    },
+    Locale {
+        code: "es",
+        name: "Español",
+        rtl: false,
+        ui: UiStrings {
+            main_navigation: "Navegación principal",
+            toggle_navigation: "Mostrar u ocultar el menú de navegación",
+            list_categories: "Categorías de listas",
+            list_content: "Contenido de la lista",
+            filter_by_tag: "Filtrar {list} por etiqueta",
+            new: "Nuevo",
+            added: "Añadido el {date}",
+            site_footer: "Pie de página del sitio",
+            feed_title: "{title}: cambios recientes",
+            languages: "Idiomas",
+        },
+    },
+    Locale {
+        code: "fr",
+        name: "Français",
+        rtl: false,
+        ui: UiStrings {
+            main_navigation: "Navigation principale",
+            toggle_navigation: "Afficher ou masquer le menu de navigation",
+            list_categories: "Catégories de listes",
+            list_content: "Contenu de la liste",
+            filter_by_tag: "Filtrer {list} par étiquette",
+            new: "Nouveau",
+            added: "Ajouté le {date}",
+            site_footer: "Pied de page du site",
+            feed_title: "{title} : nouveautés",
+            languages: "Langues",
+        },
+    },
+    Locale {
+        code: "de",
+        name: "Deutsch",
+        rtl: false,
+        ui: UiStrings {
+            main_navigation: "Hauptnavigation",
+            toggle_navigation: "Navigationsmenü ein- oder ausblenden",
+            list_categories: "Listenkategorien",
+            list_content: "Listeninhalt",
+            filter_by_tag: "{list} nach Schlagwort filtern",
+            new: "Neu",
+            added: "Hinzugefügt am {date}",
+            site_footer: "Fußzeile der Website",
+            feed_title: "{title}: Änderungen",
+            languages: "Sprachen",
+        },
+    },
+    Locale {
+        code: "it",
+        name: "Italiano",
+        rtl: false,
+        ui: UiStrings {
+            main_navigation: "Navigazione principale",
+            toggle_navigation: "Mostra o nascondi il menu di navigazione",
+            list_categories: "Categorie di elenchi",
+            list_content: "Contenuto dell'elenco",
+            filter_by_tag: "Filtra {list} per tag",
+            new: "Nuovo",
+            added: "Aggiunto il {date}",
+            site_footer: "Piè di pagina del sito",
+            feed_title: "{title}: novità",
+            languages: "Lingue",
+        },
+    },
+    Locale {
+        code: "pt",
+        name: "Português",
+        rtl: false,
+        ui: UiStrings {
+            main_navigation: "Navegação principal",
+            toggle_navigation: "Mostrar ou ocultar o menu de navegação",
+            list_categories: "Categorias de listas",
+            list_content: "Conteúdo da lista",
+            filter_by_tag: "Filtrar {list} por etiqueta",
+            new: "Novo",
+            added: "Adicionado em {date}",
+            site_footer: "Rodapé do site",
+            feed_title: "{title}: alterações recentes",
+            languages: "Idiomas",
+        },
+    },
+    Locale {
+        code: "ar",
+        name: "العربية",
+        rtl: true,
+        ui: UiStrings {
+            main_navigation: "التنقل الرئيسي",
+            toggle_navigation: "إظهار قائمة التنقل أو إخفاؤها",
+            list_categories: "فئات القوائم",
+            list_content: "محتوى القائمة",
+            filter_by_tag: "تصفية {list} حسب الوسم",
+            new: "جديد",
+            added: "أضيف في {date}",
+            site_footer: "تذييل الموقع",
+            feed_title: "{title}: آخر التغييرات",
+            languages: "اللغات",
+        },
+    },
+    Locale {
+        code: "he",
+        name: "עברית",
+        rtl: true,
+        ui: UiStrings {
+            main_navigation: "ניווט ראשי",
+            toggle_navigation: "הצגה או הסתרה של תפריט הניווט",
+            list_categories: "קטגוריות רשימות",
+            list_content: "תוכן הרשימה",
+            filter_by_tag: "סינון {list} לפי תגית",
+            new: "חדש",
+            added: "נוסף ב־{date}",
+            site_footer: "כותרת תחתונה של האתר",
+            feed_title: "{title}: שינויים אחרונים",
+            languages: "שפות",
+        },
+    },
+];
+
+pub(crate) fn find(code: &str) -> Option<&'static Locale> {
+    LOCALES.iter().find(|locale| locale.code == code)
+}
+
+pub(crate) fn validate_locale(code: &str) -> Result<()> {
+    find(code).map(|_| ()).ok_or_else(|| {
+        let known: Vec<_> = LOCALES.iter().map(|locale| locale.code).collect();
+        anyhow!("locale {code:?} must be one of {known:?}")
+    })
+}
+
+// Validation has already rejected unknown locales.
+pub(crate) fn for_site(list_of_lists: &ListOfLists) -> &'static Locale {
+    list_of_lists
+        .locale
+        .as_deref()
+        .and_then(find)
+        .unwrap_or_else(|| find(DEFAULT_LOCALE).expect("the default locale is shipped"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_placeholders_survive_translation() {
+        for locale in &LOCALES {
+            let ui = &locale.ui;
+            assert!(ui.filter_by_tag.contains("{list}"), "{}", locale.code);
+            assert!(ui.added.contains("{date}"), "{}", locale.code);
+            assert!(ui.feed_title.contains("{title}"), "{}", locale.code);
+        }
+    }
+
+    #[test]
+    fn test_validate_locale() {
+        assert!(validate_locale("he").is_ok());
+        assert_eq!(
+            r#"locale "xx" must be one of ["en", "es", "fr", "de", "it", "pt", "ar", "he"]"#,
+            validate_locale("xx").unwrap_err().to_string()
+        );
+        assert_eq!("rtl", find("ar").unwrap().dir());
+        assert_eq!("ltr", find("en").unwrap().dir());
+    }
+}
@@ -1,10 +1,20 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
 use jluszcz_rust_utils::aws;
 use jluszcz_rust_utils::cli::VerbosityArgs;
 use jluszcz_rust_utils::set_up_logger;
-use list_of_lists::{APP_NAME, generator};
-use log::debug;
+use list_of_lists::canonical::{self, canonicalize, to_canonical_json};
+use list_of_lists::convert::{self, SourceFormat};
+use list_of_lists::imports::ImportPolicy;
+use list_of_lists::{
+    APP_NAME, ExportFormat, IMPORT_ORIGINS_VAR, ListOfLists, REQUIRE_INTEGRITY_VAR,
+    VENDOR_ASSETS_VAR, generator, sri,
+};
+use log::{debug, info, warn};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::fs;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -37,6 +47,78 @@ struct Args {
     /// Minify generated site.
     #[arg(short = 'm', long)]
     minify: bool,
+
+    /// Render as of this RFC 3339 time rather than now, to preview publish_at/expire_at.
+    #[arg(short = 't', long)]
+    render_time: Option<DateTime<Utc>>,
+
+    /// Comma-separated origins footer.imports may load from, rather than the built-in CDN allowlist.
+    #[arg(short = 'i', long, env = IMPORT_ORIGINS_VAR, value_delimiter = ',')]
+    import_origins: Option<Vec<String>>,
+
+    /// Reject footer.imports tags without an integrity attribute.
+    #[arg(long, env = REQUIRE_INTEGRITY_VAR)]
+    require_integrity: bool,
+
+    /// Serve the template's CDN scripts and stylesheets from vendored copies published with the site.
+    #[arg(long, env = VENDOR_ASSETS_VAR)]
+    vendor_assets: bool,
+
+    /// Fail rather than render if the site has validation warnings.
+    #[arg(short = 's', long)]
+    strict: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compute integrity hashes for footer.imports from vendored copies and write them into the local site JSON.
+    Sri {
+        /// Directory of vendored files, laid out as <host>/<path>. Defaults to the generator bucket's 'vendor'.
+        #[arg(long)]
+        vendor_dir: Option<PathBuf>,
+    },
+    /// Print the site's visible lists (as of --render-time) to stdout rather than rendering it.
+    Export {
+        /// One of 'json', 'markdown' or 'text'.
+        #[arg(short = 'f', long)]
+        format: ExportFormat,
+    },
+    /// Convert a CSV, Markdown or OPML file into site JSON on stdout, titled by the file or the site URL.
+    Import {
+        /// File to convert.
+        file: PathBuf,
+
+        /// One of 'csv', 'markdown' or 'opml'. Defaults to the file's extension.
+        #[arg(short = 'f', long)]
+        format: Option<SourceFormat>,
+    },
+    /// Rewrite legacy footerLinks in the local site JSON, or the given files, as footer.
+    Migrate {
+        /// Files to migrate rather than the site's JSON.
+        files: Vec<PathBuf>,
+    },
+    /// Rewrite the local site JSON, or the given files, in canonical form.
+    Fmt {
+        /// Files to format rather than the site's JSON.
+        files: Vec<PathBuf>,
+
+        /// Fail rather than rewrite if any file isn't already in canonical form.
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+// The files a local-only command works on: those given, or else the site's JSON.
+fn site_files(args: &Args, files: &[PathBuf]) -> Vec<PathBuf> {
+    if files.is_empty() {
+        let generator_path = Path::new("buckets").join(&args.generator_bucket);
+        vec![generator_path.join(format!("{}.json", args.site_url))]
+    } else {
+        files.to_vec()
+    }
 }
 
 #[tokio::main]
@@ -45,12 +127,147 @@ async fn main() -> Result<()> {
     set_up_logger(APP_NAME, module_path!(), args.verbosity)?;
     debug!("Args: {args:?}");
 
-    let s3_client = if args.use_s3 {
+    match &args.command {
+        Some(Command::Sri { vendor_dir }) => sri(&args, vendor_dir.clone()).await,
+        Some(Command::Import { file, format }) => import(&args, file, *format).await,
+        Some(Command::Migrate { files }) => migrate(&args, files).await,
+        Some(Command::Fmt { files, check }) => fmt(&args, files, *check).await,
+        Some(Command::Export { format }) => export(&args, *format).await,
+        None => render(args).await,
+    }
+}
+
+async fn s3_client(args: &Args) -> Option<aws_sdk_s3::Client> {
+    if args.use_s3 {
         let aws_config = aws::config(None).await;
         Some(aws_sdk_s3::Client::new(&aws_config))
     } else {
         None
-    };
+    }
+}
+
+async fn sri(args: &Args, vendor_dir: Option<PathBuf>) -> Result<()> {
+    if args.use_s3 {
+        bail!("sri only updates local files; upload the pinned site JSON afterwards");
+    }
+    let generator_path = Path::new("buckets").join(&args.generator_bucket);
+    let vendor_dir = vendor_dir.unwrap_or_else(|| generator_path.join(generator::VENDOR_DIR));
+    let site_json = generator_path.join(format!("{}.json", args.site_url));
+    let updated = sri::pin_imports(&site_json, &vendor_dir).await?;
+    info!("Updated {updated} import(s) in {site_json:?}");
+    Ok(())
+}
+
+async fn import(args: &Args, file: &Path, format: Option<SourceFormat>) -> Result<()> {
+    let format = format
+        .or_else(|| SourceFormat::from_path(file))
+        .ok_or_else(|| anyhow!("can't tell the format of {file:?}; pass --format"))?;
+    let content = fs::read_to_string(file)
+        .await
+        .with_context(|| format!("read {file:?}"))?;
+    let imported = convert::import(&content, format, &args.site_url)
+        .with_context(|| format!("import {file:?}"))?;
+    for skipped in &imported.skipped {
+        warn!("Skipped {file:?} {skipped}");
+    }
+    info!(
+        "Imported {} list(s) from {file:?}",
+        imported.list_of_lists.lists.len()
+    );
+    print!("{}", to_canonical_json(&imported.list_of_lists)?);
+    Ok(())
+}
+
+async fn migrate(args: &Args, files: &[PathBuf]) -> Result<()> {
+    if args.use_s3 {
+        bail!("migrate only updates local files; upload the migrated site JSON afterwards");
+    }
+    for file in site_files(args, files) {
+        let content = fs::read_to_string(&file)
+            .await
+            .with_context(|| format!("read {file:?}"))?;
+        let Some(migrated) = canonical::edit(&content, ListOfLists::migrate_footer_links)
+            .with_context(|| format!("parse {file:?}"))?
+        else {
+            info!("{file:?} has no footerLinks to migrate");
+            continue;
+        };
+        // Validate as a render would, with includes read relative to the file.
+        let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+        let io = generator::Io::LocalFile {
+            generator_path: dir.clone(),
+            site_path: dir,
+        };
+        generator::validate_site_json(&io, &file.display().to_string(), &migrated)
+            .await
+            .with_context(|| format!("validate migrated {file:?}"))?;
+        fs::write(&file, migrated)
+            .await
+            .with_context(|| format!("write {file:?}"))?;
+        info!("Migrated footerLinks in {file:?}");
+    }
+    Ok(())
+}
+
+async fn fmt(args: &Args, files: &[PathBuf], check: bool) -> Result<()> {
+    if args.use_s3 {
+        bail!("fmt only updates local files; upload the formatted site JSON afterwards");
+    }
+    let mut unformatted = Vec::new();
+    for file in site_files(args, files) {
+        let content = fs::read_to_string(&file)
+            .await
+            .with_context(|| format!("read {file:?}"))?;
+        let formatted = canonicalize(&content).with_context(|| format!("format {file:?}"))?;
+        if formatted == content {
+            debug!("{file:?} is already formatted");
+        } else if check {
+            warn!("{file:?} is not formatted");
+            unformatted.push(file);
+        } else {
+            fs::write(&file, formatted)
+                .await
+                .with_context(|| format!("write {file:?}"))?;
+            info!("Formatted {file:?}");
+        }
+    }
+    if !unformatted.is_empty() {
+        bail!(
+            "{} file(s) need formatting: {unformatted:?}",
+            unformatted.len()
+        );
+    }
+    Ok(())
+}
+
+async fn export(args: &Args, format: ExportFormat) -> Result<()> {
+    let io = generator::Io::new(
+        args.site_url.clone(),
+        args.generator_bucket.clone(),
+        s3_client(args).await,
+    );
+    let now = args.render_time.unwrap_or_else(Utc::now);
+    let exported = generator::export_site(&io, &args.site_url, format, now).await?;
+    std::io::stdout().write_all(&exported)?;
+    Ok(())
+}
 
-    generator::update_site(args.site_url, args.generator_bucket, s3_client, args.minify).await
+async fn render(args: Args) -> Result<()> {
+    let s3_client = s3_client(&args).await;
+    generator::update_site(
+        args.site_url,
+        args.generator_bucket,
+        s3_client,
+        args.render_time.unwrap_or_else(Utc::now),
+        &args
+            .import_origins
+            .map_or_else(ImportPolicy::default, ImportPolicy::new)
+            .require_integrity(args.require_integrity),
+        generator::RenderOptions {
+            minify: args.minify,
+            vendor_assets: args.vendor_assets,
+            strict: args.strict,
+        },
+    )
+    .await
 }
@@ -0,0 +1,175 @@
+use crate::{ListItem, SortMode};
+use rand_chacha::ChaCha8Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use std::cmp::Ordering;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+pub(crate) fn sort_items(items: &mut [ListItem], mode: SortMode, seed: [u8; 32]) {
+    match mode {
+        SortMode::Source => {}
+        SortMode::Alpha => {
+            items.sort_by_cached_key(|i| (collation_key(i.item(), false), i.item().to_string()))
+        }
+        SortMode::AlphaCi => {
+            items.sort_by_cached_key(|i| (collation_key(i.item(), true), i.item().to_string()))
+        }
+        SortMode::Natural => items
+            .sort_by(|a, b| natural_cmp(a.item(), b.item()).then_with(|| a.item().cmp(b.item()))),
+        SortMode::Reverse => items.reverse(),
+        SortMode::RandomAtBuild => shuffle(items, &mut ChaCha8Rng::from_seed(seed)),
+    }
+}
+
+// Fisher-Yates over ChaCha8, both fixed algorithms, so a seed gives the same order
+// whatever the crate versions; rand's own shuffle and StdRng may change between releases.
+fn shuffle<T>(items: &mut [T], rng: &mut impl RngCore) {
+    for i in (1..items.len()).rev() {
+        // The modulo bias is at most len / 2^64.
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+// Approximates root-locale collation at primary strength: decomposing and
+// dropping combining marks sorts "Émile" beside "Emile" rather than after "Z".
+// It ignores the site's locale, and letters without a decomposition (ß, ø, Æ)
+// keep their code point order, after every ASCII letter.
+fn collation_key(s: &str, case_insensitive: bool) -> String {
+    let folded = s.nfd().filter(|c| !is_combining_mark(*c));
+    if case_insensitive {
+        folded.flat_map(char::to_lowercase).collect()
+    } else {
+        folded.collect()
+    }
+}
+
+// Case-insensitive, with runs of ASCII digits compared by value so "Item 2"
+// sorts before "Item 10".
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (collation_key(a, true), collation_key(b, true));
+    let (mut a, mut b) = (a.as_str(), b.as_str());
+    loop {
+        match (next_chunk(a), next_chunk(b)) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some((a_chunk, a_rest)), Some((b_chunk, b_rest))) => {
+                let ordering = match (is_digits(a_chunk), is_digits(b_chunk)) {
+                    (true, true) => numeric_cmp(a_chunk, b_chunk),
+                    _ => a_chunk.cmp(b_chunk),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                (a, b) = (a_rest, b_rest);
+            }
+        }
+    }
+}
+
+// Splits off the leading run of either digits or non-digits.
+fn next_chunk(s: &str) -> Option<(&str, &str)> {
+    let first = s.chars().next()?;
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+        .unwrap_or(s.len());
+    Some(s.split_at(end))
+}
+
+fn is_digits(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// Compares digit runs by value without parsing, so arbitrarily long runs can't overflow.
+fn numeric_cmp(a: &str, b: &str) -> Ordering {
+    let (a_trimmed, b_trimmed) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    a_trimmed
+        .len()
+        .cmp(&b_trimmed.len())
+        .then_with(|| a_trimmed.cmp(b_trimmed))
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sorted(mode: SortMode, items: &[&str]) -> Vec<String> {
+        let mut items: Vec<ListItem> = items
+            .iter()
+            .map(|i| ListItem::Item(i.to_string()))
+            .collect();
+        sort_items(&mut items, mode, [0; 32]);
+        items.iter().map(|i| i.item().to_string()).collect()
+    }
+
+    #[test]
+    fn test_source_keeps_order() {
+        assert_eq!(
+            vec!["b", "a", "c"],
+            sorted(SortMode::Source, &["b", "a", "c"])
+        );
+    }
+
+    #[test]
+    fn test_reverse() {
+        assert_eq!(
+            vec!["c", "a", "b"],
+            sorted(SortMode::Reverse, &["b", "a", "c"])
+        );
+    }
+
+    #[test]
+    fn test_alpha_is_case_sensitive() {
+        assert_eq!(
+            vec!["Banana", "apple", "cherry"],
+            sorted(SortMode::Alpha, &["cherry", "apple", "Banana"])
+        );
+    }
+
+    #[test]
+    fn test_alpha_ci_ignores_case() {
+        assert_eq!(
+            vec!["apple", "Banana", "cherry"],
+            sorted(SortMode::AlphaCi, &["cherry", "apple", "Banana"])
+        );
+    }
+
+    #[test]
+    fn test_alpha_collates_accents_with_base_letters() {
+        assert_eq!(
+            vec!["Eclair", "Émile", "Zola"],
+            sorted(SortMode::Alpha, &["Zola", "Émile", "Eclair"])
+        );
+        assert_eq!(
+            vec!["Uber", "über", "zebra"],
+            sorted(SortMode::AlphaCi, &["zebra", "über", "Uber"])
+        );
+    }
+
+    #[test]
+    fn test_natural_compares_numbers_by_value() {
+        assert_eq!(
+            vec!["item 2", "Item 10", "item 010b", "item 100"],
+            sorted(
+                SortMode::Natural,
+                &["item 100", "Item 10", "item 2", "item 010b"]
+            )
+        );
+    }
+
+    #[test]
+    fn test_random_at_build_is_a_seeded_permutation() {
+        let items = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let first = sorted(SortMode::RandomAtBuild, &items);
+        assert_eq!(first, sorted(SortMode::RandomAtBuild, &items));
+
+        let mut sorted_back = first.clone();
+        sorted_back.sort();
+        assert_eq!(items.to_vec(), sorted_back);
+
+        // Pinned, so a dependency upgrade can't silently reshuffle every site.
+        assert_eq!(vec!["c", "b", "d", "h", "a", "e", "f", "g"], first);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::Footer;
+use crate::html::url_origin;
+use crate::imports::{parse_import, with_integrity};
+use crate::include::{FOOTER, include_key, validate_key};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::{debug, info};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+// Subresource Integrity value for a file's exact bytes.
+pub fn integrity(content: &[u8]) -> String {
+    format!("sha384-{}", STANDARD.encode(Sha384::digest(content)))
+}
+
+// Whether content satisfies an integrity attribute, i.e. matches any of its hashes.
+pub(crate) fn matches_integrity(content: &[u8], integrity: &str) -> bool {
+    integrity.split_whitespace().any(|expected| {
+        let actual = match expected.split_once('-') {
+            Some(("sha256", _)) => format!("sha256-{}", STANDARD.encode(Sha256::digest(content))),
+            Some(("sha384", _)) => format!("sha384-{}", STANDARD.encode(Sha384::digest(content))),
+            Some(("sha512", _)) => format!("sha512-{}", STANDARD.encode(Sha512::digest(content))),
+            _ => return false,
+        };
+        actual == expected
+    })
+}
+
+// Vendored copies mirror their URL: https://cdn.example.com/npm/a.js lives at
+// <vendor_dir>/cdn.example.com/npm/a.js. Queries and fragments are ignored.
+pub fn vendored_path(vendor_dir: &Path, url: &str) -> Result<PathBuf> {
+    Ok(vendor_dir.join(vendored_key(url)?))
+}
+
+// The '/'-separated path of a URL's vendored copy relative to the vendor directory.
+pub(crate) fn vendored_key(url: &str) -> Result<String> {
+    let (_, host) = url_origin(url).ok_or_else(|| anyhow!("{url:?} is not an absolute URL"))?;
+    let path = url
+        .split_once("//")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map_or("", |(_, path)| path);
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    if path
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        bail!("{url:?} does not name a vendorable file");
+    }
+    Ok(format!("{host}/{path}"))
+}
+
+// Sets integrity (and crossorigin) on every footer.imports tag from its vendored
+// copy, rewriting only the changed import strings so the rest of the file keeps
+// its formatting. Returns how many imports changed.
+pub async fn pin_imports(site_json: &Path, vendor_dir: &Path) -> Result<usize> {
+    let content = fs::read_to_string(site_json)
+        .await
+        .with_context(|| format!("read {site_json:?}"))?;
+    // Only the footer matters here, so lists may still be includes.
+    let site: Value =
+        serde_json::from_str(&content).with_context(|| format!("parse {site_json:?}"))?;
+
+    // A shared footer is pinned in its own file, for every site that includes it.
+    if let Some(key) = include_key(&site[FOOTER]) {
+        validate_key(key, ".json").with_context(|| format!("footer in {site_json:?}"))?;
+        let fragment = site_json.parent().unwrap_or(Path::new("")).join(key);
+        let content = fs::read_to_string(&fragment)
+            .await
+            .with_context(|| format!("read {fragment:?}"))?;
+        let footer = serde_json::from_str(&content)
+            .with_context(|| format!("parse {fragment:?} as footer"))?;
+        return pin_footer(&fragment, content, footer, vendor_dir).await;
+    }
+
+    let Some(footer) = Option::<Footer>::deserialize(&site[FOOTER])
+        .with_context(|| format!("parse footer of {site_json:?}"))?
+    else {
+        return Ok(0);
+    };
+    pin_footer(site_json, content, footer, vendor_dir).await
+}
+
+// Pins `footer`'s imports as they're written in `file`, whose text is `content`.
+async fn pin_footer(
+    file: &Path,
+    content: String,
+    footer: Footer,
+    vendor_dir: &Path,
+) -> Result<usize> {
+    let mut integrities = HashMap::new();
+    for import in &footer.imports {
+        let tags = parse_import(import).map_err(|e| anyhow!("{import:?}: {e}"))?;
+        for tag in tags {
+            if integrities.contains_key(&tag.url) {
+                continue;
+            }
+            let path = vendored_path(vendor_dir, &tag.url)?;
+            debug!("Hashing {path:?} for {}", tag.url);
+            let bytes = fs::read(&path)
+                .await
+                .with_context(|| format!("read vendored copy of {} at {path:?}", tag.url))?;
+            integrities.insert(tag.url, integrity(&bytes));
+        }
+    }
+
+    let mut updated_content = content.clone();
+    let mut updated = 0;
+    for import in &footer.imports {
+        let pinned = with_integrity(import, |url| Ok(integrities[url].clone()))?;
+        if pinned == *import {
+            continue;
+        }
+        // serde_json escapes strings the way hand-written and generated JSON usually does.
+        let (from, to) = (
+            serde_json::to_string(import)?,
+            serde_json::to_string(&pinned)?,
+        );
+        if !updated_content.contains(&from) {
+            bail!("could not find {from} in {file:?}; re-save it with standard JSON escaping");
+        }
+        updated_content = updated_content.replace(&from, &to);
+        updated += 1;
+    }
+
+    if updated > 0 {
+        serde_json::from_str::<Value>(&updated_content)
+            .with_context(|| format!("re-parse rewritten {file:?}"))?;
+        info!("Pinning integrity for {updated} import(s) in {file:?}");
+        fs::write(file, updated_content)
+            .await
+            .with_context(|| format!("write {file:?}"))?;
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_known_integrity() {
+        // echo -n 'alert(1)' | openssl dgst -sha384 -binary | base64
+        assert_eq!(
+            "sha384-HT2E9NfWiuQ/w1PRai+hTyqW16NIoCGA/m8VQDUopfAtcz6YQjtsMmQd5uRbVDpW",
+            integrity(b"alert(1)")
+        );
+    }
+
+    #[test]
+    fn test_matches_integrity() {
+        let sha384 = integrity(b"alert(1)");
+        assert!(matches_integrity(b"alert(1)", &sha384));
+        assert!(matches_integrity(
+            b"alert(1)",
+            &format!("sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI= {sha384}")
+        ));
+        assert!(!matches_integrity(b"alert(2)", &sha384));
+        assert!(!matches_integrity(b"alert(1)", "md5-abc"));
+    }
+
+    #[test]
+    fn test_vendored_path() {
+        let vendor = Path::new("vendor");
+        assert_eq!(
+            Path::new("vendor/cdn.example.com/npm/a@1/a.min.js"),
+            vendored_path(vendor, "https://CDN.example.com/npm/a@1/a.min.js?v=2#x").unwrap()
+        );
+        assert!(vendored_path(vendor, "https://cdn.example.com/").is_err());
+        assert!(vendored_path(vendor, "https://cdn.example.com/a/../../etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pin_imports() -> Result<()> {
+        let dir = TempDir::new("sri");
+        let vendor = dir.join("vendor");
+        fs::create_dir_all(vendor.join("a.com")).await?;
+        fs::write(vendor.join("a.com/a.js"), "alert(1)").await?;
+
+        let site_json = dir.join("site.json");
+        let original = r#"{
+  "title": "Site",
+  "lists": [{"title": "Letters", "list": ["A"]}],
+  "footer": {
+    "imports": ["<script src=\"https://a.com/a.js\"></script>"],
+    "links": []
+  }
+}"#;
+        fs::write(&site_json, original).await?;
+
+        assert_eq!(1, pin_imports(&site_json, &vendor).await?);
+        let pinned = fs::read_to_string(&site_json).await?;
+        assert_eq!(
+            original.replace(
+                r#"<script src=\"https://a.com/a.js\"></script>"#,
+                r#"<script src=\"https://a.com/a.js\" integrity=\"sha384-HT2E9NfWiuQ/w1PRai+hTyqW16NIoCGA/m8VQDUopfAtcz6YQjtsMmQd5uRbVDpW\" crossorigin=\"anonymous\"></script>"#
+            ),
+            pinned
+        );
+        // Already pinned imports are left alone.
+        assert_eq!(0, pin_imports(&site_json, &vendor).await?);
+
+        // A shared footer is pinned in its own file.
+        fs::create_dir_all(dir.join("shared")).await?;
+        fs::write(
+            dir.join("shared/footer.json"),
+            r#"{"imports": ["<script src=\"https://a.com/a.js\"></script>"]}"#,
+        )
+        .await?;
+        let shared =
+            r#"{"title": "Site", "lists": [], "footer": {"include": "shared/footer.json"}}"#;
+        fs::write(&site_json, shared).await?;
+        assert_eq!(1, pin_imports(&site_json, &vendor).await?);
+        assert_eq!(shared, fs::read_to_string(&site_json).await?);
+        assert!(
+            fs::read_to_string(dir.join("shared/footer.json"))
+                .await?
+                .contains("integrity=")
+        );
+        Ok(())
+    }
+}
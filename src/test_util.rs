@@ -0,0 +1,42 @@
+use crate::generator::Io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// A scratch directory under the system temp dir, removed when dropped. Derefs to its path.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    // Names only need to be readable; each directory gets its own suffix.
+    pub(crate) fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("lol-{name}-{}-{id}", std::process::id()));
+        // Left over from a run that crashed before cleaning up.
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    // Local Io over generator/ and site/ inside the directory.
+    pub(crate) fn io(&self) -> Io {
+        Io::LocalFile {
+            generator_path: self.join("generator"),
+            site_path: self.join("site"),
+        }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
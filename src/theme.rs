@@ -0,0 +1,175 @@
+use crate::generator::Io;
+use crate::include::validate_key;
+use crate::{ColorScheme, Theme};
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::Serialize;
+use std::sync::LazyLock;
+
+// Bootstrap's bg-dark, which the navbar and footer use without a primary color.
+const DEFAULT_THEME_COLOR: &str = "#212529";
+
+// Custom CSS is inlined into every page, so it has to stay small.
+const MAX_CUSTOM_CSS_BYTES: usize = 32 * 1024;
+
+const MAX_FONT_FAMILY_LEN: usize = 200;
+
+// Theme values are written into the page's <style>, so they're limited to what
+// can't close the declaration or the element.
+pub(crate) fn validate_theme(theme: &Theme) -> Result<()> {
+    static HEX_COLOR: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^#(?:[0-9A-Fa-f]{3}){1,2}$").unwrap());
+
+    if let Some(color) = &theme.primary_color
+        && !HEX_COLOR.is_match(color)
+    {
+        bail!("theme primary_color {color:?} must be a hex color like \"#0d6efd\"");
+    }
+    if let Some(font_family) = &theme.font_family
+        && (font_family.trim().is_empty()
+            || font_family.len() > MAX_FONT_FAMILY_LEN
+            || !font_family
+                .chars()
+                .all(|c| c.is_alphanumeric() || " ,-_'\"".contains(c)))
+    {
+        bail!(
+            "theme font_family {font_family:?} must be a list of font names like \"Inter, sans-serif\", at most {MAX_FONT_FAMILY_LEN} characters"
+        );
+    }
+    if let Some(key) = &theme.custom_css {
+        validate_key(key, ".css").context("theme custom_css")?;
+    }
+    Ok(())
+}
+
+// The theme as the template uses it, with custom CSS read and derived values filled in.
+#[derive(Debug, Serialize)]
+pub(crate) struct ThemeContext {
+    color_scheme: ColorScheme,
+    theme_color: String,
+    primary_color: Option<String>,
+    // "r, g, b", for Bootstrap's --bs-*-rgb variables.
+    primary_rgb: Option<String>,
+    font_family: Option<String>,
+    custom_css: Option<String>,
+}
+
+// `custom_css` is the content of the stylesheet the theme names, if any.
+pub(crate) fn context(theme: Option<&Theme>, custom_css: Option<String>) -> ThemeContext {
+    let primary_color = theme.and_then(|theme| theme.primary_color.clone());
+    ThemeContext {
+        color_scheme: theme.map(|theme| theme.color_scheme).unwrap_or_default(),
+        theme_color: primary_color
+            .clone()
+            .unwrap_or_else(|| DEFAULT_THEME_COLOR.to_string()),
+        primary_rgb: primary_color.as_deref().map(rgb),
+        primary_color,
+        font_family: theme.and_then(|theme| theme.font_family.clone()),
+        custom_css,
+    }
+}
+
+// Validation has already checked the theme's values, but not the custom CSS it names.
+pub(crate) async fn read_custom_css(io: &Io, theme: Option<&Theme>) -> Result<Option<String>> {
+    let Some(key) = theme.and_then(|theme| theme.custom_css.as_deref()) else {
+        return Ok(None);
+    };
+    let css = io
+        .read(key)
+        .await
+        .with_context(|| format!("read theme custom_css {key}"))?;
+    check_custom_css(key, &css)?;
+    Ok(Some(css))
+}
+
+// The CSS is inlined in a <style> element, which "</style" would end early.
+fn check_custom_css(key: &str, css: &str) -> Result<()> {
+    if css.len() > MAX_CUSTOM_CSS_BYTES {
+        bail!(
+            "theme custom_css {key} is {} bytes, over the {MAX_CUSTOM_CSS_BYTES} byte limit",
+            css.len()
+        );
+    }
+    if css.to_ascii_lowercase().contains("</style") {
+        bail!("theme custom_css {key} must not contain \"</style\"");
+    }
+    Ok(())
+}
+
+// "#0d6efd" or "#06f" as "13, 110, 253" or "0, 102, 255".
+fn rgb(hex: &str) -> String {
+    let digits = hex.trim_start_matches('#');
+    let digits: String = if digits.len() == 3 {
+        digits.chars().flat_map(|c| [c, c]).collect()
+    } else {
+        digits.to_string()
+    };
+    (0..3)
+        .map(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap_or_default())
+        .map(|channel| channel.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn theme(edit: impl FnOnce(&mut Theme)) -> Theme {
+        let mut theme: Theme = serde_json::from_str("{}").unwrap();
+        edit(&mut theme);
+        theme
+    }
+
+    #[test]
+    fn test_validate_theme() {
+        assert!(
+            validate_theme(&theme(|t| {
+                t.primary_color = Some("#0D6EFD".to_string());
+                t.font_family = Some(r#""Inter Variable", system-ui, sans-serif"#.to_string());
+                t.custom_css = Some("styles/site.css".to_string());
+            }))
+            .is_ok()
+        );
+        for color in ["0d6efd", "#0d6ef", "red", "#0d6efd; color: red"] {
+            let theme = theme(|t| t.primary_color = Some(color.to_string()));
+            assert!(validate_theme(&theme).is_err(), "{color}");
+        }
+        for font_family in ["", "Inter; color: red", "Inter</style>", &"a".repeat(201)] {
+            let theme = theme(|t| t.font_family = Some(font_family.to_string()));
+            assert!(validate_theme(&theme).is_err(), "{font_family}");
+        }
+        for key in [
+            "site.css",
+            "../site.css",
+            "styles/site.json",
+            "/styles/site.css",
+        ] {
+            let theme = theme(|t| t.custom_css = Some(key.to_string()));
+            assert!(validate_theme(&theme).is_err(), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_check_custom_css() {
+        assert!(check_custom_css("a/b.css", "body { color: red; }").is_ok());
+        assert_eq!(
+            r#"theme custom_css a/b.css must not contain "</style""#,
+            check_custom_css("a/b.css", "</STYLE><script>")
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "theme custom_css a/b.css is 32769 bytes, over the 32768 byte limit",
+            check_custom_css("a/b.css", &" ".repeat(MAX_CUSTOM_CSS_BYTES + 1))
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_rgb() {
+        assert_eq!("13, 110, 253", rgb("#0d6efd"));
+        assert_eq!("0, 102, 255", rgb("#06F"));
+    }
+}